@@ -0,0 +1,29 @@
+#![no_main]
+
+use jlox::lox::STACK_SIZE;
+use jlox::Lox;
+use libfuzzer_sys::fuzz_target;
+
+/// Enough steps for interesting programs while still cutting off infinite
+/// loops quickly.
+const STEP_BUDGET: usize = 10_000;
+
+fuzz_target!(|source: &str| {
+    let source = source.to_string();
+
+    // Deep recursion needs more stack than the fuzzer's thread has
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut lox = Lox::new();
+            lox.set_output(std::io::sink());
+            lox.set_error_output(std::io::sink());
+            lox.set_step_budget(Some(STEP_BUDGET));
+            lox.set_file_access(false);
+
+            let _ = lox.run(source);
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use jlox::parser::Parser;
+use jlox::scanner::Scanner;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    if let Ok(tokens) = Scanner::new(source.to_string()).scan_tokens() {
+        let _ = Parser::new(tokens).parse();
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use jlox::scanner::Scanner;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = Scanner::new(source.to_string()).scan_tokens();
+});
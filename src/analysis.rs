@@ -0,0 +1,344 @@
+//! Static analysis of a script for editor tooling: its errors and warnings,
+//! what it declares, and which declaration each name refers to.
+//!
+//! Names are bound by the resolver, which records each declaration and the
+//! local each use resolves to. Anything else falls back to the globals, which
+//! may be declared anywhere in the script.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::CompileError;
+use crate::interpreter::Interpreter;
+use crate::lint::{self, Lint, Warning};
+use crate::parser::{Parser, Stmt, StmtKind};
+use crate::resolver::{DeclarationKind, Resolver, Symbols};
+use crate::scanner::Scanner;
+use crate::token::{Token, MAIN_FILE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Variable,
+    Parameter,
+    Function,
+    Class,
+    Method,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: Token,
+    pub kind: DefinitionKind,
+    pub signature: String,     // e.g. `fun add(a, b)`
+    pub parent: Option<usize>, // the function or class it's declared in
+    pub global: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub token: Token,
+    pub definition: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub errors: Vec<CompileError>,
+    pub warnings: Vec<Warning>,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+impl Analysis {
+    /// Whether the script compiled, so that its declarations are known.
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The definition of the name at `line` and `column`, whether that's
+    /// where it's declared or where it's used.
+    pub fn definition_at(&self, line: usize, column: usize) -> Option<usize> {
+        let reference = self
+            .references
+            .iter()
+            .find(|reference| contains(&reference.token, line, column))
+            .map(|reference| reference.definition);
+
+        reference.or_else(|| {
+            self.definitions
+                .iter()
+                .position(|definition| contains(&definition.name, line, column))
+        })
+    }
+
+    /// Every use of a definition, in source order.
+    pub fn references_to(&self, definition: usize) -> Vec<&Token> {
+        self.references
+            .iter()
+            .filter(|reference| reference.definition == definition)
+            .map(|reference| &reference.token)
+            .collect()
+    }
+
+    /// The definitions declared directly inside `parent`, or the globals if
+    /// it's `None`. Those in a block outside any function aren't either.
+    pub fn children(&self, parent: Option<usize>) -> Vec<usize> {
+        (0..self.definitions.len())
+            .filter(|&index| {
+                let definition = &self.definitions[index];
+                definition.parent == parent && (parent.is_some() || definition.global)
+            })
+            .collect()
+    }
+}
+
+/// Whether `token` covers the character at `line` and `column`, or ends just
+/// before it, as a cursor at the end of a name still refers to it.
+fn contains(token: &Token, line: usize, column: usize) -> bool {
+    let length = token.lexeme.chars().count();
+    token.line == line && token.column <= column && column <= token.column + length
+}
+
+/// Analyzes `source`, the script at `path` if it's saved to a file. If it
+/// doesn't compile, only its errors are reported.
+pub fn analyze(source: &str, path: Option<&Path>) -> Analysis {
+    let tokens = match Scanner::new(source.to_string()).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(errors) => return with_errors(errors),
+    };
+    let statements = match Parser::new(tokens).parse() {
+        Ok(statements) => statements,
+        Err(errors) => return with_errors(errors),
+    };
+    let mut interpreter = Interpreter::new();
+    if let Some(path) = path {
+        interpreter.set_script_path(path);
+    }
+    let mut resolver = Resolver::new(interpreter).recording_symbols();
+    if let Err(errors) = resolver.resolve_statements(&statements) {
+        return with_errors(errors);
+    }
+    let symbols = resolver.take_symbols().unwrap_or_default();
+
+    let (definitions, references) = index(&statements, &symbols);
+
+    Analysis {
+        errors: vec![],
+        warnings: lint::lint(&statements, &symbols, &Lint::ALL),
+        definitions,
+        references,
+    }
+}
+
+fn with_errors(errors: Vec<CompileError>) -> Analysis {
+    Analysis {
+        errors,
+        ..Default::default()
+    }
+}
+
+/// The definitions the script makes and the references to them, from the
+/// symbols the resolver recorded. Those of the modules it imports are left
+/// out.
+fn index(statements: &[Stmt], symbols: &Symbols) -> (Vec<Definition>, Vec<Reference>) {
+    let declarations = || {
+        symbols
+            .declarations
+            .iter()
+            .enumerate()
+            .filter(|(_, declaration)| declaration.file == MAIN_FILE)
+    };
+
+    // Imports are only allowed at the top level, so that's the only place to
+    // look for the modules they're from
+    let mut modules: HashMap<(usize, usize), &str> = HashMap::new();
+    for statement in statements {
+        if let StmtKind::Import(_keyword, path, Some(names)) = &statement.kind {
+            for name in names {
+                modules.insert((name.line, name.column), &path.lexeme);
+            }
+        }
+    }
+    let mut params: HashMap<usize, Vec<&str>> = HashMap::new();
+    for (_, declaration) in declarations() {
+        if let (DeclarationKind::Parameter, Some(function)) = (declaration.kind, declaration.parent)
+        {
+            params
+                .entry(function)
+                .or_default()
+                .push(&declaration.name.lexeme);
+        }
+    }
+
+    let mut definitions = vec![];
+    let mut indices: HashMap<usize, usize> = HashMap::new(); // by declaration
+    let mut globals: HashMap<&str, usize> = HashMap::new();
+    for (index, declaration) in declarations() {
+        let name = &declaration.name;
+        let signature = |prefix: &str| {
+            let params = params
+                .get(&index)
+                .map_or(String::new(), |params| params.join(", "));
+            format!("{}{}({})", prefix, name.lexeme, params)
+        };
+        let (kind, signature) = match declaration.kind {
+            DeclarationKind::Variable => (DefinitionKind::Variable, format!("var {}", name.lexeme)),
+            DeclarationKind::Parameter => (
+                DefinitionKind::Parameter,
+                format!("(parameter) {}", name.lexeme),
+            ),
+            DeclarationKind::Function => (DefinitionKind::Function, signature("fun ")),
+            DeclarationKind::Class => (DefinitionKind::Class, format!("class {}", name.lexeme)),
+            DeclarationKind::Method => {
+                let class = declaration
+                    .parent
+                    .map_or("", |class| &symbols.declarations[class].name.lexeme);
+                (DefinitionKind::Method, signature(&format!("{}.", class)))
+            }
+            DeclarationKind::Import => {
+                let module = modules.get(&(name.line, name.column)).unwrap_or(&"");
+                let signature = format!("import {{ {} }} from {}", name.lexeme, module);
+                (DefinitionKind::Variable, signature)
+            }
+        };
+
+        let definition = definitions.len();
+        indices.insert(index, definition);
+        if declaration.global {
+            // Like the interpreter, the latest global declaration wins
+            globals.insert(&name.lexeme, definition);
+        }
+        definitions.push(Definition {
+            name: name.clone(),
+            kind,
+            signature,
+            parent: declaration
+                .parent
+                .and_then(|parent| indices.get(&parent).copied()),
+            global: declaration.global,
+        });
+    }
+
+    // The resolver leaves globals to be bound at runtime, as they may be
+    // declared after they're used
+    let mut references: Vec<Reference> = symbols
+        .uses
+        .iter()
+        .filter(|name| name.file == MAIN_FILE)
+        .filter_map(|name| {
+            let definition = match name.declaration {
+                Some(declaration) => indices.get(&declaration),
+                None => globals.get(name.name.lexeme.as_str()),
+            };
+            Some(Reference {
+                token: name.name.clone(),
+                definition: *definition?,
+            })
+        })
+        .collect();
+    references.sort_by_key(|reference| (reference.token.line, reference.token.column));
+
+    (definitions, references)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+var total = 0;
+fun add(n) {
+  total = total + n;
+  return total;
+}
+class Counter {
+  bump(by) { return add(by); }
+}
+{
+  var total = 1;
+  print total;
+}
+";
+
+    fn name_at(analysis: &Analysis, line: usize, column: usize) -> Option<(usize, &str)> {
+        analysis.definition_at(line, column).map(|index| {
+            let name = &analysis.definitions[index].name;
+            (name.line, name.lexeme.as_str())
+        })
+    }
+
+    #[test]
+    fn references_resolve_through_scopes() {
+        let analysis = analyze(SOURCE, None);
+        assert!(analysis.is_valid());
+
+        // `total` in `add` is the global, in the block it's the local
+        assert_eq!(name_at(&analysis, 3, 3), Some((1, "total")));
+        assert_eq!(name_at(&analysis, 11, 9), Some((10, "total")));
+        assert_eq!(name_at(&analysis, 7, 21), Some((2, "add")));
+        assert_eq!(name_at(&analysis, 7, 25), Some((7, "by")));
+        assert_eq!(name_at(&analysis, 9, 1), None);
+    }
+
+    #[test]
+    fn references_to_a_definition() {
+        let analysis = analyze(SOURCE, None);
+        let total = analysis.definition_at(1, 5).unwrap();
+
+        let lines: Vec<usize> = analysis
+            .references_to(total)
+            .iter()
+            .map(|token| token.line)
+            .collect();
+        assert_eq!(lines, vec![3, 3, 4]);
+    }
+
+    #[test]
+    fn signatures_and_nesting() {
+        let analysis = analyze(SOURCE, None);
+
+        let top_level: Vec<&str> = analysis
+            .children(None)
+            .into_iter()
+            .map(|index| analysis.definitions[index].signature.as_str())
+            .collect();
+        assert_eq!(top_level, vec!["var total", "fun add(n)", "class Counter"]);
+
+        let counter = analysis.definition_at(6, 7).unwrap();
+        let methods: Vec<&str> = analysis
+            .children(Some(counter))
+            .into_iter()
+            .map(|index| analysis.definitions[index].signature.as_str())
+            .collect();
+        assert_eq!(methods, vec!["Counter.bump(by)"]);
+    }
+
+    #[test]
+    fn imported_names() {
+        let directory = std::env::temp_dir().join(format!("jlox-{}-analysis", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("util.lox"), "fun twice(n) { return n * 2; }").unwrap();
+
+        let source = "import { twice } from \"util.lox\";\nprint twice(1);";
+        let analysis = analyze(source, Some(&directory.join("main.lox")));
+
+        // The module's own declarations aren't the script's
+        let signatures: Vec<&str> = analysis
+            .definitions
+            .iter()
+            .map(|definition| definition.signature.as_str())
+            .collect();
+        assert_eq!(signatures, vec!["import { twice } from \"util.lox\""]);
+        assert_eq!(name_at(&analysis, 2, 7), Some((1, "twice")));
+    }
+
+    #[test]
+    fn errors_and_warnings() {
+        let analysis = analyze("print ;", None);
+        assert!(!analysis.is_valid());
+        assert_eq!(analysis.errors[0].column, 7);
+        assert!(analysis.definitions.is_empty());
+
+        let analysis = analyze("{ var unused; }", None);
+        assert_eq!(analysis.warnings[0].lint, Lint::UnusedVariable);
+    }
+}
@@ -1,6 +1,5 @@
 use crate::environment::Environment;
 use crate::interpreter::{Control, Interpreter, RuntimeError};
-use crate::lox_instance::LoxInstance;
 use crate::object::Object;
 use crate::parser::Stmt;
 use crate::token::{Literal, Token, TokenType};
@@ -22,6 +21,12 @@ pub trait Callable {
 
     fn name(&self) -> &str;
 
+    /// The name profiles show it under, which tells apart methods of
+    /// different classes, e.g. `A.m` from `B.m`.
+    fn qualified_name(&self) -> String {
+        self.name().to_string()
+    }
+
     fn check_arity(&self, paren: &Token, arguments: &[Rc<Object>]) -> Result<(), RuntimeError> {
         if arguments.len() != self.arity() {
             let message = format!(
@@ -66,6 +71,95 @@ impl Callable for Clock {
     }
 }
 
+/// The implementation of a native function. It's given the interpreter, so it
+/// can reach session state such as the script's arguments.
+pub type NativeFn =
+    fn(&mut Interpreter, &Token, Vec<Rc<Object>>) -> Result<Rc<Object>, RuntimeError>;
+
+/// A global function implemented in Rust.
+pub struct NativeFunction {
+    name: &'static str,
+    arity: usize,
+    function: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn new(name: &'static str, arity: usize, function: NativeFn) -> Self {
+        Self {
+            name,
+            arity,
+            function,
+        }
+    }
+}
+
+impl Callable for NativeFunction {
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Rc<Object>>,
+    ) -> Result<Rc<Object>, RuntimeError> {
+        self.check_arity(paren, &arguments)?;
+        (self.function)(interpreter, paren, arguments)
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+/// The implementation of a method on a built-in type, given its receiver.
+pub type NativeMethodFn = fn(&Object, &Token, Vec<Rc<Object>>) -> Result<Rc<Object>, RuntimeError>;
+
+/// A method of a built-in type such as a list, bound to its receiver.
+pub struct NativeMethod {
+    receiver: Rc<Object>,
+    name: &'static str,
+    arity: usize,
+    function: NativeMethodFn,
+}
+
+impl NativeMethod {
+    pub fn new(
+        receiver: Rc<Object>,
+        name: &'static str,
+        arity: usize,
+        function: NativeMethodFn,
+    ) -> Self {
+        Self {
+            receiver,
+            name,
+            arity,
+            function,
+        }
+    }
+}
+
+impl Callable for NativeMethod {
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Rc<Object>>,
+    ) -> Result<Rc<Object>, RuntimeError> {
+        self.check_arity(paren, &arguments)?;
+        (self.function)(&self.receiver, paren, arguments)
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LoxFunction {
     name: String,
@@ -73,6 +167,7 @@ pub struct LoxFunction {
     body: Vec<Stmt>,
     closure: Rc<RefCell<Environment>>,
     is_initializer: bool,
+    class: Option<String>, // the class it's a method of
 }
 
 impl LoxFunction {
@@ -92,20 +187,32 @@ impl LoxFunction {
             body,
             closure,
             is_initializer,
+            class: None,
         }
     }
 
-    pub fn bind(self, instance: &LoxInstance) -> LoxFunction {
-        let instance = Rc::new(Object::Instance(RefCell::new(instance.clone())));
-        self.closure.borrow_mut().define("this", instance);
+    /// Makes this a method of the class called `class`.
+    pub fn in_class(mut self, class: &str) -> Self {
+        self.class = Some(class.to_string());
+        self
+    }
 
-        LoxFunction::new(
-            self.name,
+    /// Creates a copy of this method whose closure binds `this` to
+    /// `instance`.
+    pub fn bind(&self, instance: Rc<Object>) -> LoxFunction {
+        let mut environment = Environment::from_enclosing(self.closure.clone());
+        environment.define("this", instance);
+
+        let mut method = LoxFunction::new(
+            self.name.clone(),
             &self.parameters,
             &self.body,
-            self.closure,
+            Rc::new(RefCell::new(environment)),
             self.is_initializer,
-        )
+        );
+        method.class = self.class.clone();
+
+        method
     }
 }
 
@@ -156,7 +263,10 @@ impl Callable for LoxFunction {
                     Ok(value)
                 }
             }
-            Err(Control::Error(err)) => Err(err),
+            Err(Control::Error(mut err)) => {
+                err.push_frame(&self.name, paren);
+                Err(err)
+            }
         }
     }
 
@@ -167,4 +277,11 @@ impl Callable for LoxFunction {
     fn name(&self) -> &str {
         self.name.as_str()
     }
+
+    fn qualified_name(&self) -> String {
+        match &self.class {
+            Some(class) => format!("{}.{}", class, self.name),
+            None => self.name.clone(),
+        }
+    }
 }
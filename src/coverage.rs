@@ -0,0 +1,288 @@
+//! Recording which statements ran and which way branches went, for reports
+//! in the lcov format.
+//!
+//! Every statement line and branch of a script is registered when it's
+//! compiled, so those that never run are reported too. A branch is an `if`,
+//! a `while` condition or a short-circuiting `and` or `or`, each of which
+//! has two arms: taken, and not taken.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::parser::{Expr, ExprKind, Stmt, StmtKind};
+use crate::token::{Span, MAIN_FILE};
+
+#[derive(Debug, Default)]
+pub struct Coverage {
+    lines: BTreeMap<usize, usize>, // hits by line
+    last_line: Option<usize>,      // the line of the statement that last ran
+    // Times each arm was taken, by where the branch starts
+    branches: BTreeMap<(usize, usize), [usize; 2]>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers the lines and branches of `statements`, none of which have
+    /// run yet.
+    pub fn add_statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.add_statement(statement);
+        }
+    }
+
+    fn add_statement(&mut self, statement: &Stmt) {
+        // Blocks are covered through the statements in them
+        if !matches!(statement.kind, StmtKind::Block(_)) {
+            self.lines.entry(statement.span.start_line).or_default();
+        }
+
+        match &statement.kind {
+            StmtKind::Block(statements) | StmtKind::Class(_, statements) => {
+                self.add_statements(statements)
+            }
+            StmtKind::Function(_, _, body) => self.add_statements(body),
+            StmtKind::If(condition, then_branch, else_branch) => {
+                self.add_branch(&condition.span);
+                self.add_expression(condition);
+                self.add_statement(then_branch);
+                if let Some(else_branch) = else_branch.as_ref() {
+                    self.add_statement(else_branch);
+                }
+            }
+            StmtKind::While(_, condition, body) => {
+                self.add_branch(&condition.span);
+                self.add_expression(condition);
+                self.add_statement(body);
+            }
+            StmtKind::Expression(expr) | StmtKind::Print(_, expr) => self.add_expression(expr),
+            StmtKind::Import(..) => {}
+            StmtKind::Return(_, value) | StmtKind::Var(_, value) => {
+                if let Some(value) = value {
+                    self.add_expression(value);
+                }
+            }
+        }
+    }
+
+    fn add_expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Logical(left, operator, right) => {
+                self.add_branch(&operator.span(expr.span.file));
+                self.add_expression(left);
+                self.add_expression(right);
+            }
+            ExprKind::Binary(left, _, right) => {
+                self.add_expression(left);
+                self.add_expression(right);
+            }
+            ExprKind::Call(callee, _, arguments) => {
+                self.add_expression(callee);
+                for argument in arguments {
+                    self.add_expression(argument);
+                }
+            }
+            ExprKind::Set(object, _, value) => {
+                self.add_expression(object);
+                self.add_expression(value);
+            }
+            ExprKind::Get(expr, _)
+            | ExprKind::Grouping(expr)
+            | ExprKind::Unary(_, expr)
+            | ExprKind::Assign(_, expr) => self.add_expression(expr),
+            ExprKind::Literal(_) | ExprKind::Variable(_) | ExprKind::This(_) => {}
+        }
+    }
+
+    fn add_branch(&mut self, span: &Span) {
+        self.branches
+            .entry((span.start_line, span.start_column))
+            .or_default();
+    }
+
+    /// Called before each statement runs, with the line it starts on. A
+    /// line is hit each time running moves onto it from another line, so one
+    /// with several statements is hit once per visit rather than once per
+    /// statement.
+    pub(crate) fn statement(&mut self, line: usize) {
+        if self.last_line.replace(line) != Some(line) {
+            *self.lines.entry(line).or_default() += 1;
+        }
+    }
+
+    /// Called as the branch at `span` goes one way or the other. An `if` or
+    /// `while` is found by its condition, and an `and` or `or` by its
+    /// operator, which is taken when it short-circuits.
+    pub(crate) fn branch(&mut self, span: &Span, taken: bool) {
+        // Only the main script is reported on, not the modules it imports
+        if span.file != MAIN_FILE {
+            return;
+        }
+
+        // Running is back at the branch, so a loop body on a line of its own
+        // is visited again each time around
+        self.last_line = Some(span.start_line);
+
+        let arms = self
+            .branches
+            .entry((span.start_line, span.start_column))
+            .or_default();
+        arms[usize::from(!taken)] += 1;
+    }
+
+    /// The lines with statements that never ran.
+    pub fn missed_lines(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, hits)| **hits == 0)
+            .map(|(line, _)| *line)
+            .collect()
+    }
+
+    /// How many statement lines ran, out of how many there are.
+    pub fn line_totals(&self) -> (usize, usize) {
+        let hit = self.lines.values().filter(|hits| **hits > 0).count();
+        (hit, self.lines.len())
+    }
+
+    /// How many branch arms were taken, out of how many there are.
+    pub fn branch_totals(&self) -> (usize, usize) {
+        let arms = self.branches.values().flatten();
+        let hit = arms.clone().filter(|taken| **taken > 0).count();
+        (hit, arms.count())
+    }
+
+    /// A summary of the coverage, for people rather than tools.
+    pub fn summary(&self) -> String {
+        let percent = |(hit, found): (usize, usize)| {
+            let percent = if found == 0 {
+                100.0
+            } else {
+                hit as f64 * 100.0 / found as f64
+            };
+            format!("{}/{} ({:.1}%)", hit, found, percent)
+        };
+
+        let mut summary = format!(
+            "Lines: {}\nBranches: {}\n",
+            percent(self.line_totals()),
+            percent(self.branch_totals())
+        );
+        let missed: Vec<String> = self.missed_lines().iter().map(usize::to_string).collect();
+        if !missed.is_empty() {
+            let _ = writeln!(summary, "Lines not run: {}", missed.join(", "));
+        }
+
+        summary
+    }
+
+    /// The coverage of the script at `path`, in the lcov tracefile format.
+    pub fn lcov(&self, path: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", path);
+
+        let mut previous_line = None;
+        let mut block = 0;
+        for (&(line, _), arms) in &self.branches {
+            // Branches on the same line are told apart by their block number
+            block = if previous_line == Some(line) {
+                block + 1
+            } else {
+                0
+            };
+            previous_line = Some(line);
+
+            let reached = arms.iter().any(|taken| *taken > 0);
+            for (branch, taken) in arms.iter().enumerate() {
+                let taken = if reached {
+                    taken.to_string()
+                } else {
+                    "-".to_string()
+                };
+                let _ = writeln!(lcov, "BRDA:{},{},{},{}", line, block, branch, taken);
+            }
+        }
+        let (hit, found) = self.branch_totals();
+        let _ = writeln!(lcov, "BRF:{}\nBRH:{}", found, hit);
+
+        for (line, hits) in &self.lines {
+            let _ = writeln!(lcov, "DA:{},{}", line, hits);
+        }
+        let (hit, found) = self.line_totals();
+        let _ = writeln!(lcov, "LF:{}\nLH:{}\nend_of_record", found, hit);
+
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lox::Lox;
+
+    fn cover(source: &str) -> Coverage {
+        let mut lox = Lox::new();
+        lox.set_output(std::io::sink());
+        lox.set_coverage(Coverage::new());
+        lox.run(source.to_string()).unwrap();
+
+        lox.take_coverage().unwrap()
+    }
+
+    const SOURCE: &str = "\
+fun sign(n) {
+  if (n < 0) {
+    return -1;
+  }
+  return 1;
+}
+var i = 0;
+while (i < 2 and sign(i) > 0) i = i + 1;
+";
+
+    #[test]
+    fn lines_that_ran() {
+        let coverage = cover(SOURCE);
+        assert_eq!(coverage.missed_lines(), vec![3]);
+        assert_eq!(coverage.line_totals(), (5, 6));
+    }
+
+    #[test]
+    fn branches_taken() {
+        let coverage = cover(SOURCE);
+        // The `if` is only ever false
+        assert_eq!(coverage.branch_totals(), (5, 6));
+        assert_eq!(
+            coverage.summary(),
+            "Lines: 5/6 (83.3%)\nBranches: 5/6 (83.3%)\nLines not run: 3\n"
+        );
+    }
+
+    #[test]
+    fn lines_are_hit_once_per_visit() {
+        let lcov =
+            cover("var i = 0;\nwhile (i < 3)\n  i = i + 1;\nif (i == 3) print i;\n").lcov("a.lox");
+        assert!(
+            lcov.contains("DA:1,1\nDA:2,1\nDA:3,3\nDA:4,1\n"),
+            "{}",
+            lcov
+        );
+    }
+
+    #[test]
+    fn lcov_records() {
+        let lcov =
+            cover("var a = true or false;\nif (a) print a;\nif (!a) print a;\n").lcov("a.lox");
+        assert_eq!(
+            lcov,
+            "TN:\nSF:a.lox\n\
+             BRDA:1,0,0,1\nBRDA:1,0,1,0\n\
+             BRDA:2,0,0,1\nBRDA:2,0,1,0\n\
+             BRDA:3,0,0,0\nBRDA:3,0,1,1\n\
+             BRF:6\nBRH:3\n\
+             DA:1,1\nDA:2,1\nDA:3,1\n\
+             LF:3\nLH:3\nend_of_record\n"
+        );
+    }
+}
@@ -0,0 +1,613 @@
+//! `jlox dap`, a Debug Adapter Protocol server over standard input and
+//! output, so that editors such as VS Code can debug scripts.
+//!
+//! Messages are read on a thread of their own and queued, so requests sent
+//! while the script runs, such as to pause it, are seen between statements.
+//! Requests that only make sense while it's paused wait until it is.
+
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::Display;
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+
+use serde_json::{json, Value};
+
+use jlox::debugger::{Debugger, Frontend, Paused, Resume};
+use jlox::lox::{Lox, LoxError};
+use jlox::object::Object;
+
+/// Scripts run on a single thread, which is always reported with this id.
+const THREAD_ID: u64 = 1;
+
+/// Runs the adapter until the client disconnects. If `sandbox` is set, the
+/// script it launches can't touch files.
+pub fn run_adapter(sandbox: bool) {
+    let client = Rc::new(Client::default());
+    let requests = Rc::new(read_requests());
+
+    let Some((launch, breakpoints)) = configure(&client, &requests) else {
+        return;
+    };
+    let exit_code = launch_script(&client, &requests, launch, breakpoints, sandbox);
+    client.event("exited", json!({ "exitCode": exit_code }));
+    client.event("terminated", json!({}));
+
+    // Wait for the client to let go
+    while let Ok(request) = requests.recv() {
+        match command(&request) {
+            "disconnect" | "terminate" => return client.respond(&request, json!({})),
+            "threads" => client.respond(&request, json!({ "threads": [] })),
+            _ => client.fail(&request, "The script has finished."),
+        }
+    }
+}
+
+/// Reads requests from standard input on another thread.
+fn read_requests() -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let mut input = BufReader::new(io::stdin());
+        while let Some(body) = read_message(&mut input) {
+            // Skip anything that isn't JSON rather than giving up
+            if let Ok(request) = serde_json::from_slice(&body) {
+                if sender.send(request).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Reads the body of a message, which is framed by a `Content-Length`
+/// header. Returns `None` once the input is closed.
+fn read_message(input: &mut impl BufRead) -> Option<Vec<u8>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        match header.trim() {
+            "" if length.is_some() => break,
+            header => {
+                if let Some(value) = header.strip_prefix("Content-Length:") {
+                    length = value.trim().parse().ok();
+                }
+            }
+        }
+    }
+
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    Some(body)
+}
+
+/// Sends messages to the client, numbering them as it goes.
+#[derive(Default)]
+struct Client {
+    seq: Cell<u64>,
+}
+
+impl Client {
+    fn send(&self, mut message: Value) {
+        self.seq.set(self.seq.get() + 1);
+        message["seq"] = json!(self.seq.get());
+
+        let body = message.to_string();
+        let mut output = io::stdout().lock();
+        let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = output.flush();
+    }
+
+    fn respond(&self, request: &Value, body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn fail(&self, request: &Value, message: impl Display) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message.to_string(),
+        }));
+    }
+
+    fn event(&self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+}
+
+/// Forwards what the script writes to the client.
+struct Output {
+    client: Rc<Client>,
+    category: &'static str,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = String::from_utf8_lossy(buf);
+        self.client.event(
+            "output",
+            json!({ "category": self.category, "output": output }),
+        );
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Launch {
+    program: String,
+    args: Vec<String>,
+    stop_on_entry: bool,
+    no_debug: bool,
+}
+
+fn command(request: &Value) -> &str {
+    request["command"].as_str().unwrap_or_default()
+}
+
+/// Handles requests until the script has been launched and the client has
+/// finished configuring it, returning the launch and the breakpoints set in
+/// its script.
+fn configure(client: &Client, requests: &Receiver<Value>) -> Option<(Launch, BTreeSet<usize>)> {
+    let mut launch = None;
+    let mut configured = false;
+    let mut breakpoints: HashMap<String, BTreeSet<usize>> = HashMap::new();
+
+    while let Ok(request) = requests.recv() {
+        match command(&request) {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                    "supportsTerminateRequest": true,
+                });
+                client.respond(&request, capabilities);
+                client.event("initialized", json!({}));
+            }
+            "launch" => {
+                let arguments = &request["arguments"];
+                let Some(program) = arguments["program"].as_str() else {
+                    client.fail(&request, "Expected the path of the script to debug.");
+                    continue;
+                };
+                let args = arguments["args"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|arg| arg.as_str().map(String::from))
+                    .collect();
+
+                launch = Some(Launch {
+                    program: program.to_string(),
+                    args,
+                    stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+                    no_debug: arguments["noDebug"].as_bool().unwrap_or(false),
+                });
+                client.respond(&request, json!({}));
+            }
+            "setBreakpoints" => {
+                let path = request["arguments"]["source"]["path"].as_str();
+                let lines = breakpoints
+                    .entry(path.unwrap_or_default().to_string())
+                    .or_default();
+                // Until the launch names the program, any file might be it
+                let verified = match (&launch, path) {
+                    (Some(launch), Some(path)) => same_file(path, &launch.program),
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                };
+                set_breakpoints(client, &request, lines, verified);
+            }
+            "configurationDone" => {
+                client.respond(&request, json!({}));
+                configured = true;
+            }
+            "threads" => client.respond(&request, json!({ "threads": [] })),
+            "disconnect" | "terminate" => {
+                client.respond(&request, json!({}));
+                return None;
+            }
+            command => client.fail(&request, format!("Unsupported request '{}'.", command)),
+        }
+
+        // The launch and the configuration can come in either order
+        if configured {
+            if let Some(launch) = launch.take() {
+                let lines = breakpoints
+                    .into_iter()
+                    .find(|(path, _)| same_file(path, &launch.program))
+                    .map(|(_, lines)| lines)
+                    .unwrap_or_default();
+                return Some((launch, lines));
+            }
+        }
+    }
+
+    None
+}
+
+/// Runs the script, returning its exit code.
+fn launch_script(
+    client: &Rc<Client>,
+    requests: &Rc<Receiver<Value>>,
+    launch: Launch,
+    breakpoints: BTreeSet<usize>,
+    sandbox: bool,
+) -> i32 {
+    let output = |category| Output {
+        client: client.clone(),
+        category,
+    };
+
+    let source = match std::fs::read_to_string(&launch.program) {
+        Ok(source) => source,
+        Err(err) => {
+            let message = format!("Error reading '{}': {}\n", launch.program, err);
+            let _ = output("stderr").write_all(message.as_bytes());
+            return 66;
+        }
+    };
+
+    let mut lox = Lox::new();
+    lox.set_script_path(&launch.program);
+    lox.set_args(launch.args);
+    lox.set_file_access(!sandbox);
+    // Send whole lines, rather than each piece as it's written
+    lox.set_output(LineWriter::new(output("stdout")));
+    lox.set_error_output(LineWriter::new(output("stderr")));
+    // Standard input carries the protocol, so scripts get none of it
+    lox.set_input(io::empty());
+
+    if !launch.no_debug {
+        let adapter = Adapter {
+            client: client.clone(),
+            requests: requests.clone(),
+            pending: VecDeque::new(),
+            program: launch.program.clone(),
+            reason: if launch.stop_on_entry {
+                "entry"
+            } else {
+                "breakpoint"
+            },
+            variables: vec![],
+        };
+        let mut debugger = Debugger::new(adapter).with_breakpoints(breakpoints);
+        if launch.stop_on_entry {
+            debugger = debugger.stop_on_entry();
+        }
+        lox.set_debugger(debugger);
+    }
+
+    match lox.run(source) {
+        Ok(()) => 0,
+        Err(LoxError::Compile(_)) => 65,
+        Err(LoxError::Runtime(_)) => 70,
+    }
+}
+
+/// Sets the breakpoints of one file to those requested. They're `verified`
+/// if the file is the program, which is the only one they can stop in.
+fn set_breakpoints(client: &Client, request: &Value, lines: &mut BTreeSet<usize>, verified: bool) {
+    let requested: Vec<usize> = request["arguments"]["breakpoints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|breakpoint| breakpoint["line"].as_u64())
+        .map(|line| line as usize)
+        .collect();
+
+    *lines = requested.iter().copied().collect();
+    let breakpoints: Vec<Value> = requested
+        .iter()
+        .map(|line| json!({ "verified": verified, "line": line }))
+        .collect();
+    client.respond(request, json!({ "breakpoints": breakpoints }));
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    a == b
+        || match (Path::new(a).canonicalize(), Path::new(b).canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+}
+
+/// What a `variablesReference` handed to the client refers to. They're only
+/// valid until the script resumes.
+enum Variables {
+    Locals(usize), // of the frame with this index
+    Globals,
+    Object(Rc<Object>),
+}
+
+struct Adapter {
+    client: Rc<Client>,
+    requests: Rc<Receiver<Value>>,
+    pending: VecDeque<Value>, // requests waiting for the script to pause
+    program: String,
+    reason: &'static str, // that the script will next have paused for
+    variables: Vec<Variables>,
+}
+
+impl Frontend for Adapter {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        self.variables.clear();
+        let stopped = json!({
+            "reason": self.reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        self.client.event("stopped", stopped);
+
+        loop {
+            let request = match self.pending.pop_front() {
+                Some(request) => request,
+                None => match self.requests.recv() {
+                    Ok(request) => request,
+                    // With the client gone, let the script run to the end
+                    Err(_) => {
+                        paused.breakpoints().clear();
+                        return Resume::Continue;
+                    }
+                },
+            };
+
+            let (resume, reason) = match command(&request) {
+                "continue" => (Resume::Continue, "breakpoint"),
+                "next" => (Resume::StepOver, "step"),
+                "stepIn" => (Resume::StepInto, "step"),
+                "stepOut" => (Resume::StepOut, "step"),
+                "stackTrace" => {
+                    self.stack_trace(&request, paused);
+                    continue;
+                }
+                "scopes" => {
+                    self.scopes(&request, paused);
+                    continue;
+                }
+                "variables" => {
+                    self.variables(&request, paused);
+                    continue;
+                }
+                "evaluate" => {
+                    self.evaluate(&request, paused);
+                    continue;
+                }
+                // Already paused, so there's nothing to do
+                "pause" => {
+                    self.client.respond(&request, json!({}));
+                    continue;
+                }
+                _ => {
+                    self.request(&request, paused.breakpoints());
+                    continue;
+                }
+            };
+
+            self.reason = reason;
+            self.client
+                .respond(&request, json!({ "allThreadsContinued": true }));
+            return resume;
+        }
+    }
+
+    fn interrupted(&mut self, breakpoints: &mut BTreeSet<usize>) -> bool {
+        loop {
+            let Ok(request) = self.requests.try_recv() else {
+                return false;
+            };
+
+            match command(&request) {
+                "pause" => {
+                    self.client.respond(&request, json!({}));
+                    self.reason = "pause";
+                    return true;
+                }
+                "continue" | "next" | "stepIn" | "stepOut" | "stackTrace" | "scopes"
+                | "variables" | "evaluate" => self.pending.push_back(request),
+                _ => self.request(&request, breakpoints),
+            }
+        }
+    }
+}
+
+impl Adapter {
+    /// Handles a request that can be made whether or not the script is
+    /// paused.
+    fn request(&mut self, request: &Value, breakpoints: &mut BTreeSet<usize>) {
+        match command(request) {
+            "setBreakpoints" => {
+                let path = request["arguments"]["source"]["path"].as_str();
+                if path.is_some_and(|path| same_file(path, &self.program)) {
+                    set_breakpoints(&self.client, request, breakpoints, true);
+                } else {
+                    // Breakpoints only stop in the program, not the modules it
+                    // imports
+                    set_breakpoints(&self.client, request, &mut BTreeSet::new(), false);
+                }
+            }
+            "threads" => {
+                let threads = json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] });
+                self.client.respond(request, threads);
+            }
+            "disconnect" | "terminate" => {
+                self.client.respond(request, json!({}));
+                std::process::exit(0);
+            }
+            command => self
+                .client
+                .fail(request, format!("Unsupported request '{}'.", command)),
+        }
+    }
+
+    fn stack_trace(&mut self, request: &Value, paused: &Paused) {
+        let arguments = &request["arguments"];
+        let start = arguments["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match arguments["levels"].as_u64() {
+            Some(0) | None => usize::MAX,
+            Some(levels) => levels as usize,
+        };
+
+        let name = Path::new(&self.program)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let frames: Vec<Value> = paused
+            .frames()
+            .iter()
+            .enumerate()
+            .rev()
+            .skip(start)
+            .take(levels)
+            .map(|(index, frame)| {
+                let function = if index == 0 {
+                    "script"
+                } else {
+                    &frame.function
+                };
+                json!({
+                    "id": index + 1,
+                    "name": function,
+                    "source": { "name": name, "path": self.program },
+                    "line": frame.line,
+                    "column": 1,
+                })
+            })
+            .collect();
+
+        let body = json!({ "stackFrames": frames, "totalFrames": paused.frames().len() });
+        self.client.respond(request, body);
+    }
+
+    fn scopes(&mut self, request: &Value, paused: &Paused) {
+        let Some(frame) = frame_index(request, paused) else {
+            return self.client.fail(request, "Unknown frame.");
+        };
+
+        let locals = self.reference(Variables::Locals(frame));
+        let globals = self.reference(Variables::Globals);
+        let scopes = json!([
+            { "name": "Locals", "variablesReference": locals, "expensive": false },
+            { "name": "Globals", "variablesReference": globals, "expensive": false },
+        ]);
+        self.client.respond(request, json!({ "scopes": scopes }));
+    }
+
+    fn variables(&mut self, request: &Value, paused: &Paused) {
+        let reference = request["arguments"]["variablesReference"].as_u64();
+        let Some(variables) = reference
+            .and_then(|reference| self.variables.get((reference as usize).checked_sub(1)?))
+        else {
+            return self.client.fail(request, "Unknown variables reference.");
+        };
+
+        let values = match variables {
+            Variables::Locals(frame) => {
+                let mut locals: Vec<(String, Rc<Object>)> = vec![];
+                let scopes = paused.frames()[*frame].scopes();
+                // Inner scopes come first and shadow outer ones
+                for (name, value) in scopes.into_iter().flatten() {
+                    if !locals.iter().any(|(local, _)| *local == name) {
+                        locals.push((name, value));
+                    }
+                }
+                locals
+            }
+            Variables::Globals => paused.globals(),
+            Variables::Object(object) => match &**object {
+                Object::Instance(instance) => instance.borrow().fields(),
+                Object::List(elements) => elements
+                    .iter()
+                    .enumerate()
+                    .map(|(index, element)| (index.to_string(), element.clone()))
+                    .collect(),
+                _ => vec![],
+            },
+        };
+
+        let variables: Vec<Value> = values
+            .into_iter()
+            .map(|(name, value)| {
+                json!({
+                    "name": name,
+                    "value": describe(&value),
+                    "variablesReference": self.children(&value),
+                })
+            })
+            .collect();
+        self.client
+            .respond(request, json!({ "variables": variables }));
+    }
+
+    fn evaluate(&mut self, request: &Value, paused: &mut Paused) {
+        let arguments = &request["arguments"];
+        let expression = arguments["expression"].as_str().unwrap_or_default();
+        // Without a frame, expressions are evaluated in the innermost one
+        let frame = match arguments["frameId"] {
+            Value::Null => paused.frames().len() - 1,
+            _ => match frame_index(request, paused) {
+                Some(frame) => frame,
+                None => return self.client.fail(request, "Unknown frame."),
+            },
+        };
+
+        match paused.evaluate(frame, expression) {
+            Ok(value) => {
+                let body = json!({
+                    "result": describe(&value),
+                    "variablesReference": self.children(&value),
+                });
+                self.client.respond(request, body);
+            }
+            Err(LoxError::Runtime(err)) => self.client.fail(request, err.message()),
+            Err(err) => self.client.fail(request, err),
+        }
+    }
+
+    fn reference(&mut self, variables: Variables) -> usize {
+        self.variables.push(variables);
+        self.variables.len()
+    }
+
+    /// A reference to the fields or elements of `value`, or 0 if it has none.
+    fn children(&mut self, value: &Rc<Object>) -> usize {
+        match &**value {
+            Object::Instance(_) | Object::List(_) => {
+                self.reference(Variables::Object(value.clone()))
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// The index of the frame a request is about. Frame ids are one more than
+/// their index, as clients may take 0 to mean no frame.
+fn frame_index(request: &Value, paused: &Paused) -> Option<usize> {
+    let id = request["arguments"]["frameId"].as_u64()? as usize;
+    (1..=paused.frames().len()).contains(&id).then(|| id - 1)
+}
+
+/// How a value is shown to the client: as it prints, but with strings quoted.
+fn describe(value: &Object) -> String {
+    match value {
+        Object::String(string) => format!("{:?}", string),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,184 @@
+//! `jlox debug`, a command-line debugger in the style of gdb.
+
+use std::io::Write;
+
+use jlox::debugger::{Debugger, Frame, Frontend, Paused, Resume};
+use jlox::lox::LoxError;
+
+const HELP: &str = "\
+Commands:
+  step, s             run to the next statement, stepping into calls
+  next, n             run to the next statement, stepping over calls
+  finish, fin         run until the current function returns
+  continue, c         run until the next breakpoint
+  break, b LINE       pause at LINE
+  delete, d LINE      stop pausing at LINE
+  breakpoints         list breakpoints
+  backtrace, bt       show the call stack
+  frame N             inspect the Nth call from the top of the stack
+  locals, l           show the variables in the current frame
+  print, p EXPR       evaluate EXPR in the current frame
+  list                show the source around the current line
+  help, h             show this help
+  quit, q             stop debugging and exit
+An empty line repeats the last command.";
+
+/// A debugger for `source` that takes commands from standard input. It
+/// pauses at the first statement unless there are `breakpoints`.
+pub fn debugger(source: &str, breakpoints: Vec<usize>) -> Debugger {
+    let console = Console {
+        source: source.lines().map(String::from).collect(),
+        frame: 0,
+        last_command: String::new(),
+    };
+
+    if breakpoints.is_empty() {
+        Debugger::new(console)
+    } else {
+        Debugger::new(console).with_breakpoints(breakpoints)
+    }
+}
+
+struct Console {
+    source: Vec<String>,
+    frame: usize, // selected for inspection, as an index into the stack
+    last_command: String,
+}
+
+impl Frontend for Console {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        self.frame = paused.frames().len() - 1;
+        self.show_frame(paused.frames(), self.frame);
+
+        loop {
+            print!("(jlox) ");
+            let _ = std::io::stdout().flush();
+
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    // With no more commands, let the script run to the end
+                    paused.breakpoints().clear();
+                    println!();
+                    return Resume::Continue;
+                }
+                Ok(_) => {}
+            }
+
+            let line = line.trim();
+            let command = if line.is_empty() {
+                self.last_command.clone()
+            } else {
+                line.to_string()
+            };
+            self.last_command = command.clone();
+
+            if let Some(resume) = self.command(paused, &command) {
+                return resume;
+            }
+        }
+    }
+}
+
+impl Console {
+    /// Runs a command, returning how to resume if it resumes the script.
+    fn command(&mut self, paused: &mut Paused, command: &str) -> Option<Resume> {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+
+        match name {
+            "" => {}
+            "step" | "s" => return Some(Resume::StepInto),
+            "next" | "n" => return Some(Resume::StepOver),
+            "finish" | "fin" => return Some(Resume::StepOut),
+            "continue" | "c" => return Some(Resume::Continue),
+            "break" | "b" => match argument.parse() {
+                Ok(line) => {
+                    paused.breakpoints().insert(line);
+                    println!("Breakpoint at line {}.", line);
+                }
+                Err(_) => println!("Expected a line number."),
+            },
+            "delete" | "d" => match argument.parse::<usize>() {
+                Ok(line) if paused.breakpoints().remove(&line) => {
+                    println!("Deleted the breakpoint at line {}.", line)
+                }
+                Ok(line) => println!("No breakpoint at line {}.", line),
+                Err(_) => println!("Expected a line number."),
+            },
+            "breakpoints" => {
+                let lines: Vec<String> =
+                    paused.breakpoints().iter().map(usize::to_string).collect();
+                if lines.is_empty() {
+                    println!("No breakpoints.");
+                } else {
+                    println!("Breakpoints at lines {}.", lines.join(", "));
+                }
+            }
+            "backtrace" | "bt" => {
+                for (index, frame) in paused.frames().iter().enumerate().rev() {
+                    let marker = if index == self.frame { '>' } else { ' ' };
+                    let number = paused.frames().len() - 1 - index;
+                    println!("{} #{} {}", marker, number, location(index, frame));
+                }
+            }
+            "frame" => {
+                let top = paused.frames().len() - 1;
+                match argument.parse::<usize>() {
+                    Ok(number) if number <= top => {
+                        self.frame = top - number;
+                        self.show_frame(paused.frames(), self.frame);
+                    }
+                    _ => println!("Expected a frame number from 0 to {}.", top),
+                }
+            }
+            "locals" | "l" => {
+                let scopes = paused.frames()[self.frame].scopes();
+                if scopes.iter().all(Vec::is_empty) {
+                    println!("No local variables.");
+                }
+                for (name, value) in scopes.into_iter().flatten() {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "print" | "p" => match paused.evaluate(self.frame, argument) {
+                Ok(value) => println!("{}", value),
+                Err(LoxError::Runtime(err)) => println!("{}", err.message()),
+                Err(err) => println!("{}", err),
+            },
+            "list" => {
+                let line = paused.frames()[self.frame].line;
+                for number in line.saturating_sub(3).max(1)..=line + 3 {
+                    if let Some(text) = self.source.get(number - 1) {
+                        let marker = if number == line { '>' } else { ' ' };
+                        println!("{} {:>4} | {}", marker, number, text);
+                    }
+                }
+            }
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" => std::process::exit(0),
+            _ => println!("Unknown command '{}'. Try 'help'.", name),
+        }
+
+        None
+    }
+
+    fn show_frame(&self, frames: &[Frame], index: usize) {
+        let frame = &frames[index];
+        println!("{}", location(index, frame));
+        if let Some(text) = self.source.get(frame.line.saturating_sub(1)) {
+            println!("{:>4} | {}", frame.line, text);
+        }
+    }
+}
+
+/// Where `frame` has got to. The bottom frame is the top level of the script.
+fn location(index: usize, frame: &Frame) -> String {
+    if index == 0 {
+        format!("script, line {}", frame.line)
+    } else {
+        format!("{}(), line {}", frame.function, frame.line)
+    }
+}
@@ -0,0 +1,361 @@
+//! Pausing a running script at breakpoints and stepping through it.
+//!
+//! A [`Debugger`] attached to the interpreter is consulted before each
+//! statement runs. When it decides to pause, it hands its [`Frontend`] a
+//! [`Paused`] view of the script, through which the call stack and variables
+//! can be inspected and expressions evaluated, and the frontend says how to
+//! carry on.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::lox::{LoxError, HOST_LINE};
+use crate::object::Object;
+use crate::parser::{Expr, ExprKind, Parser};
+use crate::scanner::Scanner;
+
+/// How a paused script carries on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next statement, even inside a call it makes.
+    StepInto,
+    /// Pause at the next statement in the current function or its callers.
+    StepOver,
+    /// Pause once the current function has returned.
+    StepOut,
+}
+
+/// The side of a debugging session that talks to the user.
+pub trait Frontend {
+    /// Called whenever the script pauses, to decide how it carries on.
+    fn paused(&mut self, paused: &mut Paused) -> Resume;
+
+    /// Called before each statement while the script runs, so the frontend
+    /// can change the breakpoints or ask for it to pause.
+    fn interrupted(&mut self, _breakpoints: &mut BTreeSet<usize>) -> bool {
+        false
+    }
+}
+
+/// A function call in progress, or the top level of the script.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub line: usize, // of the statement running in it
+    pub environment: Rc<RefCell<Environment>>,
+}
+
+impl Frame {
+    pub fn new(function: impl ToString, environment: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            function: function.to_string(),
+            line: HOST_LINE,
+            environment,
+        }
+    }
+
+    /// The variables visible in the frame, one scope at a time from the
+    /// innermost out. The global scope isn't included.
+    pub fn scopes(&self) -> Vec<Vec<(String, Rc<Object>)>> {
+        let mut scopes = vec![];
+        let mut environment = self.environment.clone();
+
+        loop {
+            let enclosing = environment.borrow().enclosing.clone();
+            match enclosing {
+                Some(enclosing) => {
+                    scopes.push(environment.borrow().values());
+                    environment = enclosing;
+                }
+                None => return scopes,
+            }
+        }
+    }
+}
+
+pub struct Debugger {
+    frontend: Box<dyn Frontend>,
+    breakpoints: BTreeSet<usize>,
+    step: Option<usize>, // pause at the next statement this many frames deep or less
+    last: Option<(usize, usize)>, // the depth and line of the statement that last ran
+}
+
+impl Debugger {
+    /// A debugger that pauses at the first statement of the script.
+    pub fn new(frontend: impl Frontend + 'static) -> Self {
+        Self {
+            frontend: Box::new(frontend),
+            breakpoints: BTreeSet::new(),
+            step: Some(usize::MAX),
+            last: None,
+        }
+    }
+
+    /// Pauses at statements on any of `lines` rather than at the start.
+    pub fn with_breakpoints(mut self, lines: impl IntoIterator<Item = usize>) -> Self {
+        self.breakpoints.extend(lines);
+        self.step = None;
+        self
+    }
+
+    /// Pauses at the first statement of the script as well as at any
+    /// breakpoints.
+    pub fn stop_on_entry(mut self) -> Self {
+        self.step = Some(usize::MAX);
+        self
+    }
+
+    /// Called before each statement runs, with the line it starts on. A
+    /// breakpoint or step pauses only once per line, so statements nested on
+    /// the line it paused at, such as the body of `if (a) print a;`, run on.
+    pub(crate) fn statement(&mut self, interpreter: &mut Interpreter, line: usize) {
+        let depth = interpreter.frames().len();
+        let moved = self.last.replace((depth, line)) != Some((depth, line));
+        let stepped = self.step.is_some_and(|limit| depth <= limit);
+        let interrupted = self.frontend.interrupted(&mut self.breakpoints);
+        if !interrupted && !(moved && (stepped || self.breakpoints.contains(&line))) {
+            return;
+        }
+
+        let mut paused = Paused {
+            interpreter,
+            breakpoints: &mut self.breakpoints,
+            line,
+        };
+        self.step = match self.frontend.paused(&mut paused) {
+            Resume::Continue => None,
+            Resume::StepInto => Some(usize::MAX),
+            Resume::StepOver => Some(depth),
+            Resume::StepOut => Some(depth - 1),
+        };
+    }
+}
+
+/// A script paused before running a statement.
+pub struct Paused<'a> {
+    interpreter: &'a mut Interpreter,
+    breakpoints: &'a mut BTreeSet<usize>,
+    line: usize,
+}
+
+impl Paused<'_> {
+    /// The line of the statement about to run.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The call stack, innermost call last.
+    pub fn frames(&self) -> &[Frame] {
+        self.interpreter.frames()
+    }
+
+    pub fn globals(&self) -> Vec<(String, Rc<Object>)> {
+        self.interpreter.globals()
+    }
+
+    pub fn breakpoints(&mut self) -> &mut BTreeSet<usize> {
+        self.breakpoints
+    }
+
+    /// Evaluates `source` as an expression in `frame`, as though it were
+    /// written where that frame is paused. Errors aren't reported, only
+    /// returned.
+    pub fn evaluate(&mut self, frame: usize, source: &str) -> Result<Rc<Object>, LoxError> {
+        let environment = self.frames()[frame].environment.clone();
+
+        let mut tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .map_err(LoxError::Compile)?;
+        // Keep the expression apart from any the script resolved
+        for token in &mut tokens {
+            token.line = HOST_LINE;
+        }
+        let expr = Parser::new(tokens)
+            .parse_expression()
+            .map_err(LoxError::Compile)?;
+        self.resolve(&expr, &environment);
+
+        let previous = self.interpreter.set_environment(environment);
+        let result = self.interpreter.evaluate(&expr);
+        self.interpreter.set_environment(previous);
+
+        result.map_err(LoxError::Runtime)
+    }
+
+    /// Binds the variables in `expr` to where they're defined in the live
+    /// `environment`, as the resolver would have bound them statically.
+    fn resolve(&mut self, expr: &Expr, environment: &Rc<RefCell<Environment>>) {
+        match &expr.kind {
+            ExprKind::Logical(left, _, right) | ExprKind::Binary(left, _, right) => {
+                self.resolve(left, environment);
+                self.resolve(right, environment);
+            }
+            ExprKind::Call(callee, _, arguments) => {
+                self.resolve(callee, environment);
+                for argument in arguments {
+                    self.resolve(argument, environment);
+                }
+            }
+            ExprKind::Get(object, _) => self.resolve(object, environment),
+            ExprKind::Set(object, _, value) => {
+                self.resolve(object, environment);
+                self.resolve(value, environment);
+            }
+            ExprKind::Grouping(expr) | ExprKind::Unary(_, expr) => self.resolve(expr, environment),
+            ExprKind::Literal(_) => {}
+            ExprKind::Assign(name, value) => {
+                self.resolve(value, environment);
+                self.bind(expr, &name.lexeme, environment);
+            }
+            ExprKind::Variable(name) | ExprKind::This(name) => {
+                self.bind(expr, &name.lexeme, environment)
+            }
+        }
+    }
+
+    fn bind(&mut self, expr: &Expr, name: &str, environment: &Rc<RefCell<Environment>>) {
+        let mut distance = 0;
+        let mut environment = environment.clone();
+
+        loop {
+            let enclosing = environment.borrow().enclosing.clone();
+            match enclosing {
+                // The outermost environment is the global scope, which
+                // unresolved names are looked up in
+                None => return self.interpreter.unresolve(expr),
+                Some(_) if environment.borrow().contains(name) => {
+                    return self.interpreter.resolve(expr, distance)
+                }
+                Some(enclosing) => {
+                    environment = enclosing;
+                    distance += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lox::Lox;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// Resumes with each of its steps in turn, logging where it paused and
+    /// what `watch` evaluated to there.
+    struct Script {
+        steps: Vec<Resume>,
+        watch: &'static str,
+        log: Log,
+    }
+
+    impl Frontend for Script {
+        fn paused(&mut self, paused: &mut Paused) -> Resume {
+            let frame = paused.frames().len() - 1;
+            let value = match paused.evaluate(frame, self.watch) {
+                Ok(value) => value.to_string(),
+                Err(err) => err.to_string(),
+            };
+            let function = &paused.frames()[frame].function;
+            self.log
+                .borrow_mut()
+                .push(format!("{} {} {}", paused.line(), function, value));
+
+            if self.steps.is_empty() {
+                Resume::Continue
+            } else {
+                self.steps.remove(0)
+            }
+        }
+    }
+
+    const SOURCE: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+";
+
+    fn debug(steps: Vec<Resume>, breakpoints: &[usize], watch: &'static str) -> Vec<String> {
+        debug_source(SOURCE, steps, breakpoints, watch)
+    }
+
+    fn debug_source(
+        source: &str,
+        steps: Vec<Resume>,
+        breakpoints: &[usize],
+        watch: &'static str,
+    ) -> Vec<String> {
+        let log = Log::default();
+        let script = Script {
+            steps,
+            watch,
+            log: log.clone(),
+        };
+        let mut debugger = Debugger::new(script);
+        if !breakpoints.is_empty() {
+            debugger = debugger.with_breakpoints(breakpoints.iter().copied());
+        }
+
+        let mut lox = Lox::new();
+        lox.set_output(std::io::sink());
+        lox.set_debugger(debugger);
+        lox.run(source.to_string()).unwrap();
+
+        log.take()
+    }
+
+    #[test]
+    fn stepping_into_and_out_of_calls() {
+        let steps = vec![Resume::StepOver, Resume::StepInto, Resume::StepOut];
+        assert_eq!(
+            debug(steps, &[], "1"),
+            vec!["1 script 1", "5 script 1", "2 add 1", "6 script 1"]
+        );
+    }
+
+    #[test]
+    fn stepping_over_calls() {
+        let steps = vec![Resume::StepOver, Resume::StepOver, Resume::StepOver];
+        assert_eq!(
+            debug(steps, &[], "1"),
+            vec!["1 script 1", "5 script 1", "6 script 1"]
+        );
+    }
+
+    #[test]
+    fn breakpoints_and_evaluating_in_frames() {
+        assert_eq!(
+            debug(vec![], &[3, 6], "a * 10 + b"),
+            vec!["3 add 12", "6 script Undefined variable: 'a'.\n[line 0]"]
+        );
+        assert_eq!(debug(vec![], &[3], "sum"), vec!["3 add 3"]);
+    }
+
+    #[test]
+    fn pausing_once_per_line() {
+        let source = "var a = true;\nif (a) print a;\nprint 2;\n";
+        assert_eq!(
+            debug_source(source, vec![], &[2], "a"),
+            vec!["2 script true"]
+        );
+
+        let steps = vec![Resume::StepOver, Resume::StepInto];
+        assert_eq!(
+            debug_source(source, steps, &[], "a"),
+            vec![
+                "1 script Undefined variable: 'a'.\n[line 0]",
+                "2 script true",
+                "3 script true"
+            ]
+        );
+    }
+}
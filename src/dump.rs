@@ -0,0 +1,139 @@
+use clap::ValueEnum;
+
+use jlox::error::CompileError;
+use jlox::json;
+use jlox::parser::{Parser, Stmt};
+use jlox::scanner::Scanner;
+use jlox::token::{Literal, Token};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// Human-readable text
+    Text,
+    /// JSON, for other tools
+    Json,
+}
+
+/// Prints the syntax tree of `source` without running it.
+pub fn dump_ast(source: String, format: Format) -> Result<(), Vec<CompileError>> {
+    let statements = parse(source)?;
+
+    match format {
+        Format::Text => {
+            for statement in statements {
+                println!("{}", statement);
+            }
+        }
+        Format::Json => println!("{:#}", json::program(&statements)),
+    }
+
+    Ok(())
+}
+
+fn parse(source: String) -> Result<Vec<Stmt>, Vec<CompileError>> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    Parser::new(tokens).parse()
+}
+
+/// Prints the tokens of `source` without parsing them.
+pub fn dump_tokens(source: String, format: Format) -> Result<(), Vec<CompileError>> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+
+    match format {
+        Format::Text => print!("{}", token_table(&tokens)),
+        Format::Json => println!("{:#}", json::tokens(&tokens)),
+    }
+
+    Ok(())
+}
+
+/// Lays out tokens in a table with a row per token, aligned by column.
+pub fn token_table(tokens: &[Token]) -> String {
+    let header = ["TYPE", "LEXEME", "LITERAL", "LINE", "COLUMN"].map(String::from);
+    let rows: Vec<[String; 5]> = tokens
+        .iter()
+        .map(|token| {
+            [
+                format!("{:?}", token.token_type),
+                escape_control(&token.lexeme),
+                match &token.literal {
+                    Literal::None => String::new(),
+                    Literal::String(string) => format!("{:?}", string),
+                    literal => literal.to_string(),
+                },
+                token.line.to_string(),
+                token.column.to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = format!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}  {:>w4$}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        );
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+
+    table
+}
+
+/// Escapes newlines and the like, which would break a table row, but leaves
+/// quotes alone.
+fn escape_control(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_control() {
+                c.escape_debug().to_string()
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_table_is_aligned() {
+        let tokens = Scanner::new("var name = \"hi\";\nprint 12.5;".to_string())
+            .scan_tokens()
+            .unwrap();
+
+        assert_eq!(
+            token_table(&tokens),
+            "\
+TYPE        LEXEME  LITERAL  LINE  COLUMN
+Var         var                 1       1
+Identifier  name                1       5
+Equal       =                   1      10
+String      \"hi\"    \"hi\"        1      12
+Semicolon   ;                   1      16
+Print       print               2       1
+Number      12.5    12.5        2       7
+Semicolon   ;                   2      11
+Eof                             2      12
+"
+        );
+    }
+}
@@ -29,6 +29,23 @@ impl Environment {
         self.values.insert(name.to_string(), value);
     }
 
+    /// The variables defined directly in this environment, sorted by name.
+    pub fn values(&self) -> Vec<(String, Rc<Object>)> {
+        let mut values: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        values.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        values
+    }
+
+    /// Whether `name` is defined directly in this environment.
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
     pub fn get(&self, name: &Token) -> Result<Rc<Object>, RuntimeError> {
         match self.values.get(&name.lexeme) {
             None => match &self.enclosing {
@@ -48,23 +65,20 @@ impl Environment {
         name: &Token,
     ) -> Result<Rc<Object>, RuntimeError> {
         Environment::ancestor(environment, distance)
-            .borrow()
-            .values
-            .get(&name.lexeme)
-            .cloned()
+            .and_then(|ancestor| ancestor.borrow().values.get(&name.lexeme).cloned())
             .ok_or_else(|| RuntimeError::new(name.clone(), "TODO: couldn't get_at"))
     }
 
     fn ancestor(
         mut environment: Rc<RefCell<Environment>>,
         distance: usize,
-    ) -> Rc<RefCell<Environment>> {
+    ) -> Option<Rc<RefCell<Environment>>> {
         for _ in 0..distance {
-            let refcell = environment.borrow().enclosing.as_ref().unwrap().clone();
+            let refcell = environment.borrow().enclosing.as_ref()?.clone();
             let _ = std::mem::replace(&mut environment, refcell);
         }
 
-        environment
+        Some(environment)
     }
 
     pub fn assign(&mut self, name: &Token, value: Rc<Object>) -> Result<(), RuntimeError> {
@@ -89,9 +103,12 @@ impl Environment {
         value: Rc<Object>,
     ) -> Result<(), RuntimeError> {
         Environment::ancestor(environment, distance)
-            .borrow_mut()
-            .values
-            .insert(name.lexeme.clone(), value)
+            .and_then(|ancestor| {
+                ancestor
+                    .borrow_mut()
+                    .values
+                    .insert(name.lexeme.clone(), value)
+            })
             .map(|_| ())
             .ok_or_else(|| RuntimeError::new(name.clone(), "TODO: couldn't assign_at"))
     }
@@ -0,0 +1,53 @@
+use std::fmt::Display;
+
+use crate::token::{Token, TokenType};
+
+/// An error found while scanning, parsing or resolving a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,    // 0 if unknown
+    pub length: usize,    // of the offending token, or 0 if unknown
+    pub location: String, // e.g. " at 'foo'", " at end" or empty
+    pub message: String,
+}
+
+impl CompileError {
+    pub fn new(line: usize, message: impl ToString) -> Self {
+        Self {
+            line,
+            column: 0,
+            length: 0,
+            location: String::new(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn at_token(token: &Token, message: impl ToString) -> Self {
+        let location = if token.token_type == TokenType::Eof {
+            " at end".to_string()
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+
+        Self {
+            line: token.line,
+            column: token.column,
+            length: token.lexeme.chars().count(),
+            location,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}] Error{}: {}",
+            self.line, self.location, self.message
+        )
+    }
+}
+
+impl std::error::Error for CompileError {}
@@ -0,0 +1,615 @@
+//! The canonical formatter behind `jlox fmt`.
+//!
+//! The formatter walks the syntax tree to decide the layout, but prints each
+//! token as it was written, in source order. Keeping a cursor into the source
+//! tokens that way tells it where the comments the parser never saw belong,
+//! and which `for` clauses were actually written before the parser desugared
+//! the loop into a `while`.
+
+use crate::error::CompileError;
+use crate::parser::{Expr, ExprKind, Parser, Stmt, StmtKind};
+use crate::scanner::Scanner;
+use crate::token::{Token, TokenType};
+
+/// Lines longer than this have their argument and parameter lists split over
+/// several lines.
+pub const MAX_WIDTH: usize = 80;
+
+const INDENT: &str = "  ";
+
+/// Formats a whole script. Scripts that don't parse are left alone, and their
+/// errors returned instead.
+pub fn format(source: &str) -> Result<String, Vec<CompileError>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let comments = scanner.comments().to_vec();
+    let statements = Parser::new(tokens.clone()).parse()?;
+
+    let mut formatter = Formatter::new(&tokens, &comments);
+    for statement in &statements {
+        formatter.new_line(true);
+        formatter.statement(statement);
+    }
+    formatter.flush_comments();
+
+    let mut output = String::new();
+    if source.starts_with("#!") {
+        output.push_str(source.lines().next().unwrap_or_default());
+        output.push('\n');
+    }
+    output.push_str(&formatter.output);
+    if !formatter.output.is_empty() {
+        output.push('\n');
+    }
+
+    if formatter.mismatched {
+        return Err(changed_meaning());
+    }
+    verify(&tokens, &comments, &output)?;
+
+    Ok(output)
+}
+
+/// Checks that formatting only changed the whitespace, so that a mistake in
+/// the formatter can never change or lose any code.
+fn verify(tokens: &[Token], comments: &[Token], output: &str) -> Result<(), Vec<CompileError>> {
+    let mut scanner = Scanner::new(output.to_string());
+    let formatted = scanner.scan_tokens()?;
+
+    let same_tokens = tokens.len() == formatted.len()
+        && tokens.iter().zip(&formatted).all(|(before, after)| {
+            before.token_type == after.token_type && before.lexeme == after.lexeme
+        });
+    let same_comments = comments.len() == scanner.comments().len()
+        && comments
+            .iter()
+            .zip(scanner.comments())
+            .all(|(before, after)| before.lexeme.trim_end() == after.lexeme);
+
+    if same_tokens && same_comments {
+        Ok(())
+    } else {
+        Err(changed_meaning())
+    }
+}
+
+fn changed_meaning() -> Vec<CompileError> {
+    vec![CompileError::new(
+        1,
+        "Formatting would change the meaning of this script.",
+    )]
+}
+
+struct Formatter<'a> {
+    tokens: &'a [Token],
+    comments: &'a [Token],
+    current: usize, // next token to print
+    comment: usize, // next comment to print
+    output: String,
+    indent: usize,
+    last_line: usize,  // source line the last token or comment printed ended on
+    block_start: bool, // whether nothing has been printed since a `{`
+    flat: bool,        // whether lists are being measured, so mustn't split
+    mismatched: bool,  // whether a token wasn't the one the syntax tree called for
+}
+
+impl<'a> Formatter<'a> {
+    fn new(tokens: &'a [Token], comments: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            comments,
+            current: 0,
+            comment: 0,
+            output: String::new(),
+            indent: 0,
+            last_line: 0,
+            block_start: false,
+            flat: false,
+            mismatched: false,
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.current)
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.peek()
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
+    /// The next comment, if it comes before the next token.
+    fn pending_comment(&self) -> Option<&'a Token> {
+        let comment = self.comments.get(self.comment)?;
+        match self.peek() {
+            Some(token) if (token.line, token.column) < (comment.line, comment.column) => None,
+            _ => Some(comment),
+        }
+    }
+
+    /// Prints the next token, which should be a `token_type`. If it isn't,
+    /// the formatter has lost its place in the source, and formatting fails
+    /// as it would if `verify` found the mismatch.
+    fn token(&mut self, token_type: TokenType) {
+        // Comments in the middle of a statement are kept where they are, with
+        // the rest of the statement carrying on below them
+        while let Some(comment) = self.pending_comment() {
+            if !self.output.ends_with([' ', '\n']) && !self.output.is_empty() {
+                self.output.push(' ');
+            }
+            self.output.push_str(comment.lexeme.trim_end());
+            self.output.push('\n');
+            self.push_indent(self.indent + 2);
+            self.last_line = comment.line;
+            self.comment += 1;
+        }
+
+        if !self.check(token_type) {
+            self.mismatched = true;
+        }
+        if let Some(token) = self.peek() {
+            self.output.push_str(&token.lexeme);
+            self.last_line = token.line + token.lexeme.matches('\n').count();
+            self.current += 1;
+        }
+    }
+
+    fn space(&mut self) {
+        self.output.push(' ');
+    }
+
+    fn push_indent(&mut self, indent: usize) {
+        for _ in 0..indent {
+            self.output.push_str(INDENT);
+        }
+    }
+
+    /// Prints the comments before the next token: on the end of the current
+    /// line if that's where they were written, and on their own lines
+    /// otherwise.
+    fn flush_comments(&mut self) {
+        while let Some(comment) = self.pending_comment() {
+            if comment.line == self.last_line && !self.output.is_empty() {
+                self.space();
+            } else {
+                self.start_line(comment.line, true);
+            }
+
+            self.output.push_str(comment.lexeme.trim_end());
+            self.last_line = comment.line;
+            self.comment += 1;
+        }
+    }
+
+    /// Starts a new line for something that was written on `line`, keeping a
+    /// blank line before it if there was one in the source.
+    fn start_line(&mut self, line: usize, allow_blank: bool) {
+        if !self.output.is_empty() {
+            self.output.push('\n');
+            if allow_blank && !self.block_start && line > self.last_line + 1 {
+                self.output.push('\n');
+            }
+        }
+
+        self.block_start = false;
+        self.push_indent(self.indent);
+    }
+
+    /// Starts a new line for the next token, after any comments before it.
+    fn new_line(&mut self, allow_blank: bool) {
+        self.flush_comments();
+        let line = self.peek().map_or(self.last_line, |token| token.line);
+        self.start_line(line, allow_blank);
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            // A `for` loop with an initializer is desugared into a block
+            StmtKind::Block(statements) if self.check(TokenType::For) => match &statements[..] {
+                [initializer, body] => self.for_loop(Some(initializer), body),
+                _ => self.block(statements),
+            },
+            StmtKind::Block(statements) => self.block(statements),
+            StmtKind::Import(_keyword, _path, names) => {
+                self.token(TokenType::Import);
+                self.space();
+                if let Some(names) = names {
+                    self.token(TokenType::LeftBrace);
+                    self.space();
+                    for (index, _name) in names.iter().enumerate() {
+                        if index > 0 {
+                            self.token(TokenType::Comma);
+                            self.space();
+                        }
+                        self.token(TokenType::Identifier);
+                    }
+                    self.space();
+                    self.token(TokenType::RightBrace);
+                    self.space();
+                    // `from`
+                    self.token(TokenType::Identifier);
+                    self.space();
+                }
+                self.token(TokenType::String);
+                self.token(TokenType::Semicolon);
+            }
+            StmtKind::Class(_name, methods) => {
+                self.token(TokenType::Class);
+                self.space();
+                self.token(TokenType::Identifier);
+                self.space();
+                self.open_brace();
+                for method in methods {
+                    self.new_line(true);
+                    self.function(method);
+                }
+                self.close_brace(methods.is_empty());
+            }
+            StmtKind::Expression(expr) => {
+                self.expr(expr);
+                self.token(TokenType::Semicolon);
+            }
+            StmtKind::Function(..) => {
+                self.token(TokenType::Fun);
+                self.space();
+                self.function(stmt);
+            }
+            StmtKind::If(condition, then_branch, else_branch) => {
+                self.token(TokenType::If);
+                self.space();
+                self.token(TokenType::LeftParen);
+                self.expr(condition);
+                self.token(TokenType::RightParen);
+                self.body(then_branch);
+
+                if let Some(else_branch) = else_branch.as_ref() {
+                    // Comments go before the `else`, which can then only
+                    // follow the `}` if none ended the line
+                    self.flush_comments();
+                    if matches!(then_branch.kind, StmtKind::Block(_)) && self.output.ends_with('}')
+                    {
+                        self.space();
+                    } else {
+                        self.new_line(false);
+                    }
+                    self.token(TokenType::Else);
+
+                    if self.check(TokenType::If) {
+                        self.space();
+                        self.statement(else_branch);
+                    } else {
+                        self.body(else_branch);
+                    }
+                }
+            }
+            StmtKind::Print(_keyword, expr) => {
+                self.token(TokenType::Print);
+                self.space();
+                self.expr(expr);
+                self.token(TokenType::Semicolon);
+            }
+            StmtKind::Return(_keyword, value) => {
+                self.token(TokenType::Return);
+                if let Some(value) = value {
+                    self.space();
+                    self.expr(value);
+                }
+                self.token(TokenType::Semicolon);
+            }
+            StmtKind::Var(_name, initializer) => {
+                self.token(TokenType::Var);
+                self.space();
+                self.token(TokenType::Identifier);
+                if let Some(initializer) = initializer {
+                    self.space();
+                    self.token(TokenType::Equal);
+                    self.space();
+                    self.expr(initializer);
+                }
+                self.token(TokenType::Semicolon);
+            }
+            StmtKind::While(keyword, ..) if keyword.token_type == TokenType::For => {
+                self.for_loop(None, stmt)
+            }
+            StmtKind::While(_keyword, condition, body) => {
+                self.token(TokenType::While);
+                self.space();
+                self.token(TokenType::LeftParen);
+                self.expr(condition);
+                self.token(TokenType::RightParen);
+                self.body(body);
+            }
+        }
+    }
+
+    /// Prints a `for` loop, undoing the parser's desugaring. Which clauses
+    /// were written is read off the tokens, since a missing condition looks
+    /// just like `true` once desugared.
+    fn for_loop(&mut self, initializer: Option<&Stmt>, desugared: &Stmt) {
+        let StmtKind::While(_keyword, condition, body) = &desugared.kind else {
+            return self.statement(desugared);
+        };
+
+        self.token(TokenType::For);
+        self.space();
+        self.token(TokenType::LeftParen);
+
+        match initializer {
+            Some(initializer) => self.statement(initializer),
+            None => self.token(TokenType::Semicolon),
+        }
+
+        if !self.check(TokenType::Semicolon) {
+            self.space();
+            self.expr(condition);
+        }
+        self.token(TokenType::Semicolon);
+
+        let body = match &body.kind {
+            StmtKind::Block(statements) if !self.check(TokenType::RightParen) => {
+                match &statements[..] {
+                    [body, increment] => {
+                        if let StmtKind::Expression(increment) = &increment.kind {
+                            self.space();
+                            self.expr(increment);
+                        }
+                        body
+                    }
+                    _ => &**body,
+                }
+            }
+            _ => &**body,
+        };
+        self.token(TokenType::RightParen);
+
+        self.body(body);
+    }
+
+    /// Prints the body of an `if`, `else` or loop. An `if` as the body goes
+    /// on a line of its own, indented, so that an `else` after it lines up
+    /// with the `if` it belongs to.
+    fn body(&mut self, body: &Stmt) {
+        if matches!(body.kind, StmtKind::If(..)) {
+            self.indent += 1;
+            self.new_line(false);
+            self.statement(body);
+            self.indent -= 1;
+        } else {
+            self.space();
+            self.statement(body);
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.open_brace();
+        for statement in statements {
+            self.new_line(true);
+            self.statement(statement);
+        }
+        self.close_brace(statements.is_empty());
+    }
+
+    fn open_brace(&mut self) {
+        self.token(TokenType::LeftBrace);
+        self.indent += 1;
+        self.block_start = true;
+    }
+
+    fn close_brace(&mut self, empty: bool) {
+        if empty && self.pending_comment().is_none() {
+            self.indent -= 1;
+            self.block_start = false;
+            self.token(TokenType::RightBrace);
+            return;
+        }
+
+        self.flush_comments();
+        self.indent -= 1;
+        self.start_line(self.last_line, false);
+        self.token(TokenType::RightBrace);
+    }
+
+    /// Prints a function declaration or method, from its name onwards.
+    fn function(&mut self, function: &Stmt) {
+        let StmtKind::Function(_name, params, body) = &function.kind else {
+            return self.statement(function);
+        };
+
+        self.token(TokenType::Identifier);
+        self.list(params, |formatter, _param| {
+            formatter.token(TokenType::Identifier)
+        });
+        self.space();
+        self.block(body);
+    }
+
+    /// Prints a parenthesized, comma-separated list, splitting it over
+    /// several lines if it doesn't fit on one.
+    fn list<T>(&mut self, items: &[T], print: impl Fn(&mut Self, &T)) {
+        let start = (
+            self.current,
+            self.comment,
+            self.output.len(),
+            self.last_line,
+        );
+
+        let flat = self.flat;
+        self.flat = true;
+        self.token(TokenType::LeftParen);
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                self.token(TokenType::Comma);
+                self.space();
+            }
+            print(self, item);
+        }
+        self.token(TokenType::RightParen);
+        self.flat = flat;
+
+        let printed = &self.output[start.2..];
+        let line = &self.output[self.output.rfind('\n').map_or(0, |index| index + 1)..];
+        let fits = !printed.contains('\n') && line.chars().count() <= MAX_WIDTH;
+        if flat || fits || items.is_empty() {
+            return;
+        }
+
+        // Print it again, with an item on each line
+        (self.current, self.comment, _, self.last_line) = start;
+        self.output.truncate(start.2);
+
+        self.token(TokenType::LeftParen);
+        self.indent += 1;
+        for (index, item) in items.iter().enumerate() {
+            self.new_line(false);
+            print(self, item);
+            if index + 1 < items.len() {
+                self.token(TokenType::Comma);
+            }
+        }
+        self.flush_comments();
+        self.indent -= 1;
+        self.start_line(self.last_line, false);
+        self.token(TokenType::RightParen);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Logical(left, operator, right) | ExprKind::Binary(left, operator, right) => {
+                self.expr(left);
+                self.space();
+                self.token(operator.token_type);
+                self.space();
+                self.expr(right);
+            }
+            ExprKind::Call(callee, _paren, arguments) => {
+                self.expr(callee);
+                self.list(arguments, |formatter, argument| formatter.expr(argument));
+            }
+            ExprKind::Get(object, _name) => {
+                self.expr(object);
+                self.token(TokenType::Dot);
+                self.token(TokenType::Identifier);
+            }
+            ExprKind::Set(object, _name, value) => {
+                self.expr(object);
+                self.token(TokenType::Dot);
+                self.token(TokenType::Identifier);
+                self.space();
+                self.token(TokenType::Equal);
+                self.space();
+                self.expr(value);
+            }
+            ExprKind::Grouping(expr) => {
+                self.token(TokenType::LeftParen);
+                self.expr(expr);
+                self.token(TokenType::RightParen);
+            }
+            ExprKind::Literal(_) => {
+                let token_type = self.peek().map_or(TokenType::Nil, |token| token.token_type);
+                self.token(token_type);
+            }
+            ExprKind::Unary(operator, right) => {
+                self.token(operator.token_type);
+                self.expr(right);
+            }
+            ExprKind::Variable(_name) => self.token(TokenType::Identifier),
+            ExprKind::Assign(_name, value) => {
+                self.token(TokenType::Identifier);
+                self.space();
+                self.token(TokenType::Equal);
+                self.space();
+                self.expr(value);
+            }
+            ExprKind::This(_keyword) => self.token(TokenType::This),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), expected, "not idempotent");
+    }
+
+    #[test]
+    fn spacing_and_indentation() {
+        assert_formats(
+            "var a=1;fun f( x,y ){return x+-y;}\nclass A{m(){this.x=a;}}",
+            "var a = 1;\nfun f(x, y) {\n  return x + -y;\n}\nclass A {\n  m() {\n    this.x = a;\n  }\n}\n",
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_formats(
+            "if(a)print 1;else if(b){print 2;}else{}\nwhile(true)\n  a = a(1);",
+            "if (a) print 1;\nelse if (b) {\n  print 2;\n} else {}\nwhile (true) a = a(1);\n",
+        );
+    }
+
+    #[test]
+    fn dangling_else() {
+        assert_formats(
+            "if (a) if (b) print 1; else print 2;",
+            "if (a)\n  if (b) print 1;\n  else print 2;\n",
+        );
+        assert_formats(
+            "if(a)if(b)print 1;else print 2;else print 3;\nwhile(a)if(b){}",
+            "if (a)\n  if (b) print 1;\n  else print 2;\nelse print 3;\nwhile (a)\n  if (b) {}\n",
+        );
+    }
+
+    #[test]
+    fn comment_before_else() {
+        assert_formats(
+            "if (a) {\n  print 1;\n} // x\nelse {\n  print 2;\n}",
+            "if (a) {\n  print 1;\n} // x\nelse {\n  print 2;\n}\n",
+        );
+        assert_formats(
+            "if (a) {\n} \n// x\nelse print 2;",
+            "if (a) {}\n// x\nelse print 2;\n",
+        );
+    }
+
+    #[test]
+    fn for_loop_clauses() {
+        assert_formats(
+            "for(var i=0;i<3;i=i+1){print i;}\nfor(;;)print 1;\nfor(i=0;;){}\nfor(;true;)print 1;",
+            "for (var i = 0; i < 3; i = i + 1) {\n  print i;\n}\nfor (;;) print 1;\nfor (i = 0;;) {}\nfor (; true;) print 1;\n",
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        assert_formats(
+            "// heading\n\n\nvar a = 1; // trailing\n{\n// inside\n\n  print a;\n  // last\n}\n// end",
+            "// heading\n\nvar a = 1; // trailing\n{\n  // inside\n\n  print a;\n  // last\n}\n// end\n",
+        );
+    }
+
+    #[test]
+    fn long_argument_lists_are_split() {
+        let arguments = ["\"a fairly long string\""; 4].join(", ");
+        assert_formats(
+            &format!("print f({});", arguments),
+            "print f(\n  \"a fairly long string\",\n  \"a fairly long string\",\n  \"a fairly long string\",\n  \"a fairly long string\"\n);\n",
+        );
+    }
+
+    #[test]
+    fn shebang_is_kept() {
+        assert_formats(
+            "#!/usr/bin/env jlox\nprint  1;",
+            "#!/usr/bin/env jlox\nprint 1;\n",
+        );
+    }
+
+    #[test]
+    fn invalid_scripts_are_not_formatted() {
+        assert!(format("print (1;").is_err());
+    }
+}
@@ -1,52 +1,213 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
+use std::io::{self, BufRead, BufReader, Write};
 use std::ops::{Deref, Not};
+use std::path::PathBuf;
 use std::rc::Rc;
 
 use crate::callable::{Callable, Clock, LoxFunction};
+use crate::coverage::Coverage;
+use crate::debugger::{Debugger, Frame};
 use crate::environment::Environment;
+use crate::lox::HOST_LINE;
 use crate::lox_class::LoxClass;
+use crate::module::Modules;
+use crate::natives;
 use crate::object::Object;
-use crate::parser::{Expr, Stmt};
-use crate::token::{Token, TokenType};
+use crate::parser::{Expr, ExprKind, Stmt, StmtKind};
+use crate::profiler::Profiler;
+use crate::token::{Token, TokenType, MAIN_FILE};
 
-#[derive(Default)]
 pub struct Interpreter {
     environment: Rc<RefCell<Environment>>,
     globals: Rc<RefCell<Environment>>,
     locals: HashMap<Expr, usize>,
+    output: Box<dyn Write>,
+    error_output: Box<dyn Write>,
+    input: Box<dyn BufRead>,
+    depth: usize, // how deeply evaluation is nested, across calls
+    step_budget: Option<usize>,
+    args: Vec<String>,
+    file_access: bool,
+    debugger: Option<Box<Debugger>>,
+    frames: Vec<Frame>, // only tracked while debugging
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    pub(crate) modules: Modules,
+}
+
+/// How deeply statements and expressions may be evaluated inside each other,
+/// counting those in every call still running, before a call is stopped with
+/// a stack overflow error rather than overflowing the interpreter's own
+/// stack. Past the last call, nesting is bounded by the parser.
+const MAX_DEPTH: usize = 10_000;
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let mut i: Self = Default::default();
+        let globals = Rc::new(RefCell::new(builtins()));
+
+        Self {
+            // Alias the initial environment to the globals environment
+            environment: globals.clone(),
+            globals,
+            locals: HashMap::default(),
+            output: Box::new(io::stdout()),
+            error_output: Box::new(io::stderr()),
+            input: Box::new(BufReader::new(io::stdin())),
+            depth: 0,
+            step_budget: None,
+            args: vec![],
+            file_access: true,
+            debugger: None,
+            frames: vec![],
+            profiler: None,
+            coverage: None,
+            modules: Modules::default(),
+        }
+    }
+
+    /// Sets the file scripts are run from, which their imports are found
+    /// relative to.
+    pub fn set_script_path(&mut self, path: impl Into<PathBuf>) {
+        self.modules.set_script(path);
+    }
+
+    /// Limits how many loop iterations and calls may run, after which a
+    /// runtime error is raised. `None` allows unlimited steps.
+    pub fn set_step_budget(&mut self, step_budget: Option<usize>) {
+        self.step_budget = step_budget;
+    }
+
+    /// Spends one step of the budget, if there is one.
+    fn step(&mut self, token: &Token) -> Result<(), RuntimeError> {
+        match &mut self.step_budget {
+            Some(0) => Err(RuntimeError::new(token.clone(), "Step budget exhausted.")),
+            Some(remaining) => {
+                *remaining -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Redirects the output of `print` statements.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Redirects where errors are reported.
+    pub fn set_error_output(&mut self, error_output: impl Write + 'static) {
+        self.error_output = Box::new(error_output);
+    }
 
-        // Define native function `clock` in the global scope
-        i.globals
-            .borrow_mut()
-            .define("clock", Rc::new(Object::Callable(Box::new(Clock {}))));
+    /// Replaces the source that scripts read input from.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Box::new(input);
+    }
+
+    /// Sets the arguments returned by the `args` native.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
 
-        // Alias the initial environment to the globals environment
-        i.environment = i.globals.clone();
+    /// Allows or denies the natives that touch files, such as `readFile`.
+    pub fn set_file_access(&mut self, allowed: bool) {
+        self.file_access = allowed;
+    }
+
+    pub fn file_access(&self) -> bool {
+        self.file_access
+    }
+
+    /// Attaches a debugger, which is consulted before each statement runs.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(Box::new(debugger));
+        self.frames = vec![Frame::new("script", self.globals.clone())];
+    }
 
-        i
+    /// The call stack, innermost call last. It's only tracked while a
+    /// debugger is attached, and is empty otherwise.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Attaches a profiler, which times every call and statement.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Detaches the profiler, once it has finished timing.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        let mut profiler = self.profiler.take()?;
+        profiler.finish();
+        Some(profiler)
+    }
+
+    /// Attaches a coverage recorder, which counts the statements that run
+    /// and the branches they take.
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn coverage_mut(&mut self) -> Option<&mut Coverage> {
+        self.coverage.as_mut()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Replaces the current environment, returning the previous one.
+    pub(crate) fn set_environment(
+        &mut self,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Rc<RefCell<Environment>> {
+        std::mem::replace(&mut self.environment, environment)
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut self.output
+    }
+
+    pub fn error_output(&mut self) -> &mut dyn Write {
+        &mut self.error_output
+    }
+
+    pub fn input(&mut self) -> &mut dyn BufRead {
+        &mut self.input
     }
 
     pub fn evaluate(&mut self, expr: &Expr) -> Result<Rc<Object>, RuntimeError> {
-        match expr {
-            Expr::Logical(left, operator, right) => self.evaluate_logical(left, operator, right),
-            Expr::Binary(left, operator, right) => self.evaluate_binary(left, operator, right),
-            Expr::Grouping(expr) => self.evaluate(expr),
-            Expr::Literal(lit) => Ok(Rc::new(Object::from(lit))),
-            Expr::Unary(operator, expr) => self.evaluate_unary(operator, expr),
-            Expr::Variable(name) => self.lookup_variable(name, expr),
-            Expr::Assign(name, expr) => self.assign_variable(name, expr),
-            Expr::Call(callee, paren, args) => self.evaluate_call(callee, paren, args),
-            Expr::Get(object, name) => self.evaluate_get(object, name),
-            Expr::Set(object, name, value) => self.evaluate_set(object, name, value),
-            Expr::This(keyword) => self.lookup_variable(keyword, expr),
-        }
+        self.depth += 1;
+        let result = match &expr.kind {
+            ExprKind::Logical(left, operator, right) => {
+                self.evaluate_logical(left, operator, right)
+            }
+            ExprKind::Binary(left, operator, right) => self.evaluate_binary(left, operator, right),
+            ExprKind::Grouping(expr) => self.evaluate(expr),
+            ExprKind::Literal(lit) => Ok(Rc::new(Object::from(lit))),
+            ExprKind::Unary(operator, expr) => self.evaluate_unary(operator, expr),
+            ExprKind::Variable(name) => self.lookup_variable(name, expr),
+            ExprKind::Assign(name, value) => self.assign_variable(name, value, expr),
+            ExprKind::Call(callee, paren, args) => self.evaluate_call(callee, paren, args),
+            ExprKind::Get(object, name) => self.evaluate_get(object, name),
+            ExprKind::Set(object, name, value) => self.evaluate_set(object, name, value),
+            ExprKind::This(keyword) => self.lookup_variable(keyword, expr),
+        };
+        self.depth -= 1;
+
+        result
     }
 
     fn evaluate_call(
@@ -61,24 +222,91 @@ impl Interpreter {
             arguments.iter().map(|arg| self.evaluate(arg)).collect();
         let arguments = arguments?;
 
-        match callee.deref() {
+        self.call(&callee, paren, arguments)
+    }
+
+    pub fn call(
+        &mut self,
+        callee: &Object,
+        paren: &Token,
+        arguments: Vec<Rc<Object>>,
+    ) -> Result<Rc<Object>, RuntimeError> {
+        self.step(paren)?;
+
+        if self.depth >= MAX_DEPTH {
+            return Err(RuntimeError::new(paren.clone(), "Stack overflow."));
+        }
+
+        let function = match callee {
+            Object::Callable(fun) => fun.name(),
+            Object::Class(klass) => klass.name(),
+            _ => "",
+        };
+        let debugging = !self.frames.is_empty();
+        if debugging {
+            self.frames
+                .push(Frame::new(function, self.environment.clone()));
+        }
+        if let Some(profiler) = &mut self.profiler {
+            match callee {
+                Object::Callable(fun) => profiler.enter(&fun.qualified_name()),
+                _ => profiler.enter(function),
+            }
+        }
+
+        let result = match callee {
             Object::Callable(fun) => fun.call(self, paren, arguments),
             Object::Class(klass) => klass.call(self, paren, arguments),
             _ => Err(RuntimeError::new(
                 paren.clone(),
                 format!("'{}' is not callable", callee),
             )),
+        };
+
+        if debugging {
+            self.frames.pop();
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
         }
+
+        result
+    }
+
+    pub fn global(&self, name: &Token) -> Result<Rc<Object>, RuntimeError> {
+        self.globals.borrow().get(name)
+    }
+
+    /// Every global variable, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Rc<Object>)> {
+        self.globals.borrow().values()
     }
 
     pub fn evaluate_stmt(&mut self, stmt: &Stmt) -> Result<(), Control> {
-        match stmt {
-            Stmt::Expression(expr) => {
+        self.depth += 1;
+        let result = self.execute(stmt);
+        self.depth -= 1;
+
+        result
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Control> {
+        if !self.frames.is_empty() || self.profiler.is_some() || self.coverage.is_some() {
+            self.trace(stmt);
+        }
+
+        match &stmt.kind {
+            StmtKind::Expression(expr) => {
                 self.evaluate(expr)?;
                 Ok(())
             }
-            Stmt::If(expr, then_branch, else_branch) => {
-                if self.evaluate(expr)?.is_truthy() {
+            StmtKind::If(expr, then_branch, else_branch) => {
+                let condition = self.evaluate(expr)?.is_truthy();
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.branch(&expr.span, condition);
+                }
+
+                if condition {
                     self.evaluate_stmt(then_branch)?;
                 } else if let Some(else_branch) = else_branch.as_ref() {
                     self.evaluate_stmt(else_branch)?;
@@ -86,36 +314,46 @@ impl Interpreter {
 
                 Ok(())
             }
-            Stmt::Print(expr) => {
+            StmtKind::Print(keyword, expr) => {
                 let object = self.evaluate(expr)?;
-                println!("{}", object);
+                writeln!(self.output, "{}", object)
+                    .map_err(|err| RuntimeError::new(keyword.clone(), err))?;
                 Ok(())
             }
-            Stmt::Var(name, expr) => match expr {
+            StmtKind::Var(name, expr) => match expr {
                 Some(expr) => {
                     let value = self.evaluate(expr)?;
-                    self.environment.borrow_mut().define(name, value);
+                    self.environment.borrow_mut().define(&name.lexeme, value);
                     Ok(())
                 }
                 None => {
                     self.environment
                         .borrow_mut()
-                        .define(name, Rc::new(Object::Nil));
+                        .define(&name.lexeme, Rc::new(Object::Nil));
                     Ok(())
                 }
             },
-            Stmt::Block(statements) => {
+            StmtKind::Block(statements) => {
                 let environment = Environment::from_enclosing(self.environment.clone());
                 self.execute_block(statements, environment)
             }
-            Stmt::While(condition, body) => {
-                while self.evaluate(condition)?.is_truthy() {
+            StmtKind::While(keyword, condition, body) => {
+                loop {
+                    let looping = self.evaluate(condition)?.is_truthy();
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.branch(&condition.span, looping);
+                    }
+                    if !looping {
+                        break;
+                    }
+
+                    self.step(keyword)?;
                     self.evaluate_stmt(body)?;
                 }
 
                 Ok(())
             }
-            Stmt::Function(name, params, body) => {
+            StmtKind::Function(name, params, body) => {
                 let function = LoxFunction::new(
                     name.lexeme.clone(),
                     params,
@@ -127,26 +365,49 @@ impl Interpreter {
                 self.environment.borrow_mut().define(&name.lexeme, object);
                 Ok(())
             }
-            Stmt::Return(_keyword, None) => Err(Control::Return(Rc::new(Object::Nil))),
-            Stmt::Return(_keyword, Some(expr)) => Err(Control::Return(self.evaluate(expr)?)),
-            Stmt::Class(class_name, class_methods) => {
+            StmtKind::Import(keyword, _path, names) => {
+                // The resolver loaded the module, and reported any it couldn't
+                let Some(file) = self.modules.imported(&stmt.span) else {
+                    let message = "Module was never loaded.";
+                    return Err(RuntimeError::new(keyword.clone(), message).into());
+                };
+                let module = self.run_module(file)?;
+
+                let values = module.borrow().values();
+                for (name, value) in values {
+                    let imported = match names {
+                        Some(names) => names.iter().any(|imported| imported.lexeme == name),
+                        None => self.modules.get(file).names.contains(&name),
+                    };
+                    if imported {
+                        self.environment.borrow_mut().define(&name, value);
+                    }
+                }
+
+                Ok(())
+            }
+            StmtKind::Return(_keyword, None) => Err(Control::Return(Rc::new(Object::Nil))),
+            StmtKind::Return(_keyword, Some(expr)) => Err(Control::Return(self.evaluate(expr)?)),
+            StmtKind::Class(class_name, class_methods) => {
                 self.environment
                     .borrow_mut()
                     .define(&class_name.lexeme, Rc::new(Object::Nil)); // TODO
 
                 let mut methods: HashMap<String, LoxFunction> = HashMap::default();
                 for method in class_methods {
-                    if let Stmt::Function(method_name, parameters, body) = method {
+                    if let StmtKind::Function(method_name, parameters, body) = &method.kind {
                         let function = LoxFunction::new(
                             method_name.lexeme.clone(),
                             parameters,
                             body,
                             self.environment.clone(),
                             method_name.lexeme == "init",
-                        );
+                        )
+                        .in_class(&class_name.lexeme);
                         methods.insert(method_name.lexeme.clone(), function);
                     } else {
-                        unreachable!("Statement ({:?}) wasn't a method.", method);
+                        let message = "Class body may only contain methods.";
+                        return Err(RuntimeError::new(class_name.clone(), message).into());
                     }
                 }
 
@@ -160,6 +421,57 @@ impl Interpreter {
         }
     }
 
+    /// Runs the module in `file`, unless it has already run, returning its
+    /// global scope.
+    fn run_module(&mut self, file: usize) -> Result<Rc<RefCell<Environment>>, Control> {
+        let module = self.modules.get_mut(file);
+        if let Some(environment) = &module.environment {
+            return Ok(environment.clone());
+        }
+
+        let environment = Rc::new(RefCell::new(builtins()));
+        module.environment = Some(environment.clone());
+        let statements = module.statements.clone();
+
+        let previous = self.set_environment(environment.clone());
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.evaluate_stmt(statement));
+        self.environment = previous;
+
+        result.map(|()| environment)
+    }
+
+    /// Tells the profiler and coverage that `stmt` is about to run, records
+    /// where the current frame has got to, and gives the debugger the chance
+    /// to pause.
+    fn trace(&mut self, stmt: &Stmt) {
+        // Blocks are stepped through one statement at a time, and only the
+        // script itself is followed, not the modules it imports
+        if matches!(stmt.kind, StmtKind::Block(_)) || stmt.span.file != MAIN_FILE {
+            return;
+        }
+        let line = stmt.span.start_line;
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.statement(line);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.statement(line);
+        }
+
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+            frame.environment = self.environment.clone();
+        }
+
+        // While it's paused, statements the debugger runs don't pause again
+        if let Some(mut debugger) = self.debugger.take() {
+            debugger.statement(self, line);
+            self.debugger = Some(debugger);
+        }
+    }
+
     pub fn execute_block(
         &mut self,
         statements: &[Stmt],
@@ -190,7 +502,10 @@ impl Interpreter {
         match (operator.token_type, &right) {
             (TokenType::Minus, _) => (-right.deref()).context(operator, "Operand must be a number"),
             (TokenType::Bang, _) => Ok(!right.deref()),
-            _ => unreachable!("unary expression with bad operator: {}", operator),
+            _ => Err(RuntimeError::new(
+                operator.clone(),
+                "Unknown unary operator.",
+            )),
         }
     }
 
@@ -202,15 +517,19 @@ impl Interpreter {
     ) -> Result<Rc<Object>, RuntimeError> {
         let left = self.evaluate(left)?;
 
-        if operator.token_type == TokenType::And {
-            if !left.is_truthy() {
-                return Ok(left);
-            }
-        } else if left.is_truthy() {
-            return Ok(left);
+        let short_circuit = match operator.token_type {
+            TokenType::And => !left.is_truthy(),
+            _ => left.is_truthy(),
+        };
+        if let Some(coverage) = &mut self.coverage {
+            coverage.branch(&operator.span(right.span.file), short_circuit);
         }
 
-        self.evaluate(right)
+        if short_circuit {
+            Ok(left)
+        } else {
+            self.evaluate(right)
+        }
     }
 
     fn evaluate_binary(
@@ -246,7 +565,10 @@ impl Interpreter {
                 .assert_numbers(operator),
             TokenType::BangEqual => Ok(Rc::new((*left).eq(&right).not().into())),
             TokenType::EqualEqual => Ok(Rc::new((*left).eq(&right).into())),
-            _ => unreachable!("not possible operands: {:?}, {:?}", left, right),
+            _ => Err(RuntimeError::new(
+                operator.clone(),
+                "Unknown binary operator.",
+            )),
         }
     }
 
@@ -254,21 +576,47 @@ impl Interpreter {
         self.locals.insert(expr.clone(), depth);
     }
 
+    /// Forgets how `expr` was resolved, so it's looked up as a global.
+    pub(crate) fn unresolve(&mut self, expr: &Expr) {
+        self.locals.remove(expr);
+    }
+
+    /// The global scope of the code that's running, which is either the
+    /// script's or that of a module it imported.
+    fn global_scope(&self) -> Rc<RefCell<Environment>> {
+        let mut environment = self.environment.clone();
+        loop {
+            let enclosing = environment.borrow().enclosing.clone();
+            match enclosing {
+                Some(enclosing) => environment = enclosing,
+                None => return environment,
+            }
+        }
+    }
+
     fn lookup_variable(&self, name: &Token, expr: &Expr) -> Result<Rc<Object>, RuntimeError> {
         match self.locals.get(expr) {
             Some(distance) => Environment::get_at(self.environment.clone(), *distance, name),
-            None => self.globals.borrow().get(name),
+            None => self.global_scope().borrow().get(name),
         }
     }
 
-    fn assign_variable(&mut self, name: &Token, expr: &Expr) -> Result<Rc<Object>, RuntimeError> {
-        let value = self.evaluate(expr)?;
+    fn assign_variable(
+        &mut self,
+        name: &Token,
+        value: &Expr,
+        expr: &Expr,
+    ) -> Result<Rc<Object>, RuntimeError> {
+        let value = self.evaluate(value)?;
 
         match self.locals.get(expr) {
             Some(distance) => {
                 Environment::assign_at(self.environment.clone(), *distance, name, value.clone())?
             }
-            None => self.globals.borrow_mut().assign(name, value.clone())?,
+            None => self
+                .global_scope()
+                .borrow_mut()
+                .assign(name, value.clone())?,
         };
 
         Ok(value)
@@ -277,21 +625,24 @@ impl Interpreter {
     fn evaluate_get(&mut self, object: &Expr, name: &Token) -> Result<Rc<Object>, RuntimeError> {
         let object = self.evaluate(object)?;
 
-        if let Object::Instance(instance) = &*object {
-            if let Some(property) = instance.borrow().get(name) {
-                Ok(property)
-            } else {
-                Err(RuntimeError::new(
+        let property = match &*object {
+            Object::Instance(instance) => instance.borrow().get(name, &object),
+            Object::List(_) | Object::String(_) => natives::method(&object, &name.lexeme),
+            Object::Namespace(namespace) => namespace.get(&name.lexeme),
+            _ => {
+                return Err(RuntimeError::new(
                     name.clone(),
-                    format!("Undefined property '{}'.", name.lexeme),
+                    "Only instances have properties.",
                 ))
             }
-        } else {
-            Err(RuntimeError::new(
+        };
+
+        property.ok_or_else(|| {
+            RuntimeError::new(
                 name.clone(),
-                "Only instances have properties.",
-            ))
-        }
+                format!("Undefined property '{}'.", name.lexeme),
+            )
+        })
     }
 
     fn evaluate_set(
@@ -315,6 +666,17 @@ impl Interpreter {
     }
 }
 
+/// A new global scope, with the natives defined in it.
+fn builtins() -> Environment {
+    let mut globals = Environment::new();
+
+    // Define native function `clock` in the global scope
+    globals.define("clock", Rc::new(Object::Callable(Box::new(Clock {}))));
+    natives::define_functions(&mut globals);
+
+    globals
+}
+
 #[derive(Debug)]
 pub enum Control {
     Return(Rc<Object>),
@@ -342,13 +704,51 @@ impl From<RuntimeError> for Control {
 pub struct RuntimeError {
     operator: Token,
     message: String,
+    trace: Vec<(String, usize)>, // (function, line of the call site)
 }
 
 impl RuntimeError {
     pub fn new(operator: Token, message: impl ToString) -> Self {
         let message = message.to_string();
 
-        Self { operator, message }
+        Self {
+            operator,
+            message,
+            trace: vec![],
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn line(&self) -> usize {
+        self.operator.line
+    }
+
+    /// Records that the error unwound out of `function`, which was called
+    /// from `paren`.
+    pub fn push_frame(&mut self, function: &str, paren: &Token) {
+        self.trace.push((function.to_string(), paren.line));
+    }
+
+    /// Formats the error along with the Lox call stack at the point it was
+    /// raised, innermost call first.
+    pub fn stack_trace(&self) -> String {
+        let mut trace = self.message.clone();
+        let mut line = self.operator.line;
+
+        for (function, call_line) in &self.trace {
+            trace += &format!("\n[line {}] in {}()", line, function);
+            line = *call_line;
+        }
+
+        // Calls made from host code don't originate in a script
+        if line != HOST_LINE {
+            trace += &format!("\n[line {}] in script", line);
+        }
+
+        trace
     }
 }
 
@@ -0,0 +1,311 @@
+//! JSON representations of tokens and syntax trees, for external tools to
+//! consume.
+//!
+//! Every node is an object with a `type` naming its variant, a `span` giving
+//! where it starts and ends in the source, and its children under the names
+//! used in the [`ExprKind`] and [`StmtKind`] definitions.
+
+use serde_json::{json, Value};
+
+use crate::parser::{Expr, ExprKind, Stmt, StmtKind};
+use crate::token::{Literal, Span, Token};
+
+/// A whole program, as an array of statements.
+pub fn program(statements: &[Stmt]) -> Value {
+    Value::Array(statements.iter().map(stmt).collect())
+}
+
+pub fn stmt(stmt: &Stmt) -> Value {
+    let span = &stmt.span;
+    match &stmt.kind {
+        StmtKind::Block(statements) => node(
+            "Block",
+            span,
+            json!({
+                "statements": program(statements),
+            }),
+        ),
+        StmtKind::Class(name, methods) => node(
+            "Class",
+            span,
+            json!({
+                "name": name.lexeme,
+                "methods": program(methods),
+            }),
+        ),
+        StmtKind::If(condition, then_branch, else_branch) => node(
+            "If",
+            span,
+            json!({
+                "condition": expr(condition),
+                "then": self::stmt(then_branch),
+                "else": else_branch.as_ref().as_ref().map(self::stmt),
+            }),
+        ),
+        StmtKind::Expression(expression) => node(
+            "Expression",
+            span,
+            json!({
+                "expression": expr(expression),
+            }),
+        ),
+        StmtKind::Function(name, params, body) => node(
+            "Function",
+            span,
+            json!({
+                "name": name.lexeme,
+                "params": params.iter().map(|param| &param.lexeme).collect::<Vec<_>>(),
+                "body": program(body),
+            }),
+        ),
+        StmtKind::Import(_keyword, path, names) => node(
+            "Import",
+            span,
+            json!({
+                "path": literal(&path.literal),
+                "names": names
+                    .as_ref()
+                    .map(|names| names.iter().map(|name| &name.lexeme).collect::<Vec<_>>()),
+            }),
+        ),
+        StmtKind::Print(_keyword, expression) => node(
+            "Print",
+            span,
+            json!({
+                "expression": expr(expression),
+            }),
+        ),
+        StmtKind::Return(_keyword, value) => node(
+            "Return",
+            span,
+            json!({
+                "value": value.as_ref().map(expr),
+            }),
+        ),
+        StmtKind::Var(name, initializer) => node(
+            "Var",
+            span,
+            json!({
+                "name": name.lexeme,
+                "initializer": initializer.as_ref().map(expr),
+            }),
+        ),
+        StmtKind::While(_keyword, condition, body) => node(
+            "While",
+            span,
+            json!({
+                "condition": expr(condition),
+                "body": self::stmt(body),
+            }),
+        ),
+    }
+}
+
+pub fn expr(expr: &Expr) -> Value {
+    let span = &expr.span;
+    match &expr.kind {
+        ExprKind::Logical(left, operator, right) | ExprKind::Binary(left, operator, right) => {
+            let kind = match &expr.kind {
+                ExprKind::Logical(..) => "Logical",
+                _ => "Binary",
+            };
+
+            node(
+                kind,
+                span,
+                json!({
+                    "operator": operator.lexeme,
+                    "left": self::expr(left),
+                    "right": self::expr(right),
+                }),
+            )
+        }
+        ExprKind::Call(callee, _paren, arguments) => node(
+            "Call",
+            span,
+            json!({
+                "callee": self::expr(callee),
+                "arguments": arguments.iter().map(self::expr).collect::<Vec<_>>(),
+            }),
+        ),
+        ExprKind::Get(object, name) => node(
+            "Get",
+            span,
+            json!({
+                "object": self::expr(object),
+                "name": name.lexeme,
+            }),
+        ),
+        ExprKind::Set(object, name, value) => node(
+            "Set",
+            span,
+            json!({
+                "object": self::expr(object),
+                "name": name.lexeme,
+                "value": self::expr(value),
+            }),
+        ),
+        ExprKind::Grouping(expression) => node(
+            "Grouping",
+            span,
+            json!({
+                "expression": self::expr(expression),
+            }),
+        ),
+        ExprKind::Literal(value) => node(
+            "Literal",
+            span,
+            json!({
+                "value": literal(value),
+            }),
+        ),
+        ExprKind::Unary(operator, right) => node(
+            "Unary",
+            span,
+            json!({
+                "operator": operator.lexeme,
+                "right": self::expr(right),
+            }),
+        ),
+        ExprKind::Variable(name) => node(
+            "Variable",
+            span,
+            json!({
+                "name": name.lexeme,
+            }),
+        ),
+        ExprKind::Assign(name, value) => node(
+            "Assign",
+            span,
+            json!({
+                "name": name.lexeme,
+                "value": self::expr(value),
+            }),
+        ),
+        ExprKind::This(_) => node("This", span, json!({})),
+    }
+}
+
+pub fn tokens(tokens: &[Token]) -> Value {
+    Value::Array(tokens.iter().map(token).collect())
+}
+
+pub fn token(token: &Token) -> Value {
+    json!({
+        "type": format!("{:?}", token.token_type),
+        "lexeme": token.lexeme,
+        "literal": literal(&token.literal),
+        "line": token.line,
+        "column": token.column,
+    })
+}
+
+pub fn literal(literal: &Literal) -> Value {
+    match literal {
+        Literal::None | Literal::Nil => Value::Null,
+        Literal::String(string) => json!(string),
+        Literal::Number(number) => json!(number.into_inner()),
+        Literal::True => json!(true),
+        Literal::False => json!(false),
+    }
+}
+
+pub fn span(span: &Span) -> Value {
+    json!({
+        "file": span.file,
+        "start_line": span.start_line,
+        "start_column": span.start_column,
+        "end_line": span.end_line,
+        "end_column": span.end_column,
+    })
+}
+
+/// Adds the `type` and `span` of a node to its `fields`.
+fn node(kind: &str, span: &Span, fields: Value) -> Value {
+    let mut node = json!({
+        "type": kind,
+        "span": self::span(span),
+    });
+
+    if let (Some(node), Value::Object(fields)) = (node.as_object_mut(), fields) {
+        node.extend(fields);
+    }
+
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn expression_statement() {
+        let json = program(&parse("-a + 1.5;"));
+
+        assert_eq!(json[0]["type"], "Expression");
+        let binary = &json[0]["expression"];
+        assert_eq!(binary["type"], "Binary");
+        assert_eq!(binary["operator"], "+");
+        assert_eq!(
+            binary["span"],
+            json!({
+                "file": 0,
+                "start_line": 1,
+                "start_column": 1,
+                "end_line": 1,
+                "end_column": 9,
+            })
+        );
+        assert_eq!(binary["left"]["type"], "Unary");
+        assert_eq!(binary["left"]["right"]["name"], "a");
+        assert_eq!(binary["right"]["value"], 1.5);
+    }
+
+    #[test]
+    fn optional_children_are_null() {
+        let json = program(&parse("var a;\nif (a) print nil;"));
+
+        assert_eq!(json[0]["initializer"], Value::Null);
+        assert_eq!(json[1]["then"]["span"]["start_line"], 2);
+        assert_eq!(json[1]["then"]["expression"]["span"]["start_column"], 14);
+        assert_eq!(json[1]["then"]["expression"]["value"], Value::Null);
+        assert_eq!(json[1]["else"], Value::Null);
+    }
+
+    #[test]
+    fn token_positions() {
+        let tokens = Scanner::new("var a =\n  \"b\";".to_string())
+            .scan_tokens()
+            .unwrap();
+        let json = super::tokens(&tokens);
+
+        assert_eq!(
+            json[3],
+            json!({
+                "type": "String",
+                "lexeme": "\"b\"",
+                "literal": "b",
+                "line": 2,
+                "column": 3,
+            })
+        );
+        assert_eq!(json[5]["type"], "Eof");
+    }
+
+    #[test]
+    fn functions_and_classes() {
+        let json = program(&parse("class A { f(x, y) { return this; } }"));
+
+        let method = &json[0]["methods"][0];
+        assert_eq!(method["type"], "Function");
+        assert_eq!(method["params"], json!(["x", "y"]));
+        assert_eq!(method["body"][0]["value"]["type"], "This");
+    }
+}
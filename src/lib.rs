@@ -0,0 +1,25 @@
+pub mod analysis;
+pub mod callable;
+pub mod coverage;
+pub mod debugger;
+pub mod environment;
+pub mod error;
+pub mod formatter;
+pub mod interpreter;
+pub mod json;
+pub mod lint;
+pub mod lox;
+pub mod lox_class;
+pub mod lox_instance;
+pub mod module;
+pub mod natives;
+pub mod object;
+pub mod parser;
+pub mod profiler;
+pub mod resolver;
+pub mod scanner;
+pub mod token;
+
+pub use interpreter::RuntimeError;
+pub use lox::Lox;
+pub use object::Object;
@@ -0,0 +1,581 @@
+//! Warnings about code that's valid, but probably not what was meant.
+//!
+//! The linter walks the syntax tree after the resolver has accepted it, for
+//! lints about its shape, and goes through the declarations and uses of
+//! names the resolver recorded for lints about how they're bound.
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::CompileError;
+use crate::interpreter::Interpreter;
+use crate::natives;
+use crate::parser::{Expr, ExprKind, FunctionKind, Parser, Stmt, StmtKind};
+use crate::resolver::{Declaration, DeclarationKind, Resolver, Symbols, Use};
+use crate::scanner::Scanner;
+use crate::token::{Literal, Span, Token, TokenType, MAIN_FILE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    UnusedFunction,
+    Shadowing,
+    UnreachableCode,
+    UndeclaredAssignment,
+    CapturedThis,
+    MixedComparison,
+    EmptyBlock,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 9] = [
+        Lint::UnusedVariable,
+        Lint::UnusedParameter,
+        Lint::UnusedFunction,
+        Lint::Shadowing,
+        Lint::UnreachableCode,
+        Lint::UndeclaredAssignment,
+        Lint::CapturedThis,
+        Lint::MixedComparison,
+        Lint::EmptyBlock,
+    ];
+
+    /// The name used to refer to the lint on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedParameter => "unused-parameter",
+            Lint::UnusedFunction => "unused-function",
+            Lint::Shadowing => "shadowing",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::UndeclaredAssignment => "undeclared-assignment",
+            Lint::CapturedThis => "captured-this",
+            Lint::MixedComparison => "mixed-comparison",
+            Lint::EmptyBlock => "empty-block",
+        }
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Lint {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Lint::ALL
+            .into_iter()
+            .find(|lint| lint.name() == name)
+            .ok_or_else(|| format!("unknown lint '{}'", name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub span: Span, // where the problem is
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}, column {}] Warning: {} [{}]",
+            self.span.start_line, self.span.start_column, self.message, self.lint
+        )
+    }
+}
+
+/// Scans, parses and resolves `source`, the script at `path` if it's from a
+/// file, then lints it with the given lints.
+pub fn lint_source(
+    source: &str,
+    path: Option<&Path>,
+    lints: &[Lint],
+) -> Result<Vec<Warning>, Vec<CompileError>> {
+    let tokens = Scanner::new(source.to_string()).scan_tokens()?;
+    let statements = Parser::new(tokens).parse()?;
+    let mut interpreter = Interpreter::new();
+    if let Some(path) = path {
+        interpreter.set_script_path(path);
+    }
+    let mut resolver = Resolver::new(interpreter).recording_symbols();
+    resolver.resolve_statements(&statements)?;
+    let symbols = resolver.take_symbols().unwrap_or_default();
+
+    Ok(lint(&statements, &symbols, lints))
+}
+
+/// Lints a program that has already been resolved, given the symbols the
+/// resolver recorded for it, returning warnings from the given lints in the
+/// order they appear in the source.
+pub fn lint(statements: &[Stmt], symbols: &Symbols, lints: &[Lint]) -> Vec<Warning> {
+    let mut linter = Linter::new(statements);
+    linter.statements(statements);
+    linter.symbols(statements, symbols);
+
+    let mut warnings: Vec<Warning> = linter
+        .warnings
+        .into_iter()
+        .filter(|warning| lints.contains(&warning.lint))
+        .collect();
+    warnings.sort_by_key(|warning| warning.span);
+
+    warnings
+}
+
+struct Linter {
+    current_function: Vec<FunctionKind>,
+    class_depth: usize,
+    file: usize, // the statements are from
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn new(statements: &[Stmt]) -> Self {
+        Self {
+            current_function: vec![],
+            class_depth: 0,
+            file: statements
+                .first()
+                .map_or(MAIN_FILE, |statement| statement.span.file),
+            warnings: vec![],
+        }
+    }
+
+    fn warn(&mut self, lint: Lint, span: Span, message: String) {
+        self.warnings.push(Warning {
+            lint,
+            span,
+            message,
+        });
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        let mut returned = false;
+
+        for statement in statements {
+            if returned {
+                self.warn(
+                    Lint::UnreachableCode,
+                    statement.span,
+                    "Unreachable code after 'return'.".to_string(),
+                );
+                returned = false;
+            }
+
+            self.statement(statement);
+
+            if let StmtKind::Return(..) = &statement.kind {
+                returned = true;
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Block(statements) => {
+                if statements.is_empty() {
+                    self.warn(Lint::EmptyBlock, statement.span, "Empty block.".to_string());
+                }
+
+                self.statements(statements);
+            }
+            StmtKind::Class(_name, methods) => {
+                self.class_depth += 1;
+                for method in methods {
+                    if let StmtKind::Function(method_name, _params, body) = &method.kind {
+                        let kind = if method_name.lexeme == "init" {
+                            FunctionKind::Initializer
+                        } else {
+                            FunctionKind::Method
+                        };
+                        self.function(body, kind);
+                    }
+                }
+                self.class_depth -= 1;
+            }
+            StmtKind::Expression(expr) | StmtKind::Print(_, expr) => self.expression(expr),
+            StmtKind::Import(..) => {}
+            StmtKind::Function(_name, _params, body) => self.function(body, FunctionKind::Function),
+            StmtKind::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch.as_ref() {
+                    self.statement(else_branch);
+                }
+            }
+            StmtKind::Return(_, value) | StmtKind::Var(_, value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            StmtKind::While(_keyword, condition, body) => {
+                self.expression(condition);
+                self.statement(body);
+            }
+        }
+    }
+
+    fn function(&mut self, body: &[Stmt], kind: FunctionKind) {
+        self.current_function.push(kind);
+        self.statements(body);
+        self.current_function.pop();
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Binary(left, operator, right) => {
+                self.comparison(left, operator, right);
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Logical(left, _operator, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call(callee, _paren, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            ExprKind::Get(object, _name) => self.expression(object),
+            ExprKind::Set(object, _name, value) => {
+                self.expression(value);
+                self.expression(object);
+            }
+            ExprKind::Grouping(expr) | ExprKind::Unary(_, expr) | ExprKind::Assign(_, expr) => {
+                self.expression(expr)
+            }
+            ExprKind::Literal(_) | ExprKind::Variable(_) => {}
+            ExprKind::This(keyword) => {
+                if self.class_depth > 0
+                    && matches!(self.current_function.last(), Some(FunctionKind::Function))
+                {
+                    self.warn(
+                        Lint::CapturedThis,
+                        self.span(keyword),
+                        "'this' in a nested function refers to the enclosing method's instance."
+                            .to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Warns about comparisons between literals of different types, which
+    /// are either always false or fail at runtime.
+    fn comparison(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        let outcome = match operator.token_type {
+            TokenType::EqualEqual => "is always false",
+            TokenType::BangEqual => "is always true",
+            TokenType::Less
+            | TokenType::LessEqual
+            | TokenType::Greater
+            | TokenType::GreaterEqual => "fails at runtime",
+            _ => return,
+        };
+
+        if let (Some(left), Some(right)) = (literal_type(left), literal_type(right)) {
+            if left != right {
+                self.warn(
+                    Lint::MixedComparison,
+                    self.span(operator),
+                    format!("Comparing {} with {} {}.", left, right, outcome),
+                );
+            }
+        }
+    }
+
+    /// Warns about names that are never read, shadow others, or are assigned
+    /// without being declared, going by how the resolver bound them.
+    fn symbols(&mut self, statements: &[Stmt], symbols: &Symbols) {
+        let declarations = || {
+            symbols
+                .declarations
+                .iter()
+                .enumerate()
+                .filter(|(_, declaration)| declaration.file == self.file)
+        };
+        let uses: Vec<&Use> = symbols
+            .uses
+            .iter()
+            .filter(|name| name.file == self.file)
+            .collect();
+
+        // Functions may assign globals declared after them, so any global
+        // the script declares counts, along with the natives
+        let mut globals: HashSet<&str> = natives::NAMES.into_iter().collect();
+        globals.extend(
+            declarations()
+                .filter(|(_, declaration)| declaration.global)
+                .map(|(_, declaration)| declaration.name.lexeme.as_str()),
+        );
+        let imports_everything = statements
+            .iter()
+            .any(|statement| matches!(statement.kind, StmtKind::Import(_, _, None)));
+
+        let mut warnings = vec![];
+        for (index, declaration) in declarations() {
+            let read = uses.iter().any(|name| {
+                !name.assignment
+                    && match name.declaration {
+                        Some(declaration) => declaration == index,
+                        None => declaration.global && name.name.lexeme == declaration.name.lexeme,
+                    }
+            });
+            if !read {
+                warnings.extend(unused(declaration));
+            }
+
+            if let Some(shadowed) = declaration.shadows {
+                let line = symbols.declarations[shadowed].name.line;
+                warnings.push((
+                    Lint::Shadowing,
+                    &declaration.name,
+                    format!(
+                        "'{}' shadows a declaration on line {}.",
+                        declaration.name.lexeme, line
+                    ),
+                ));
+            }
+        }
+
+        for name in uses {
+            let declared = name.declaration.is_some()
+                || globals.contains(name.name.lexeme.as_str())
+                || imports_everything;
+            if name.assignment && !declared {
+                warnings.push((
+                    Lint::UndeclaredAssignment,
+                    &name.name,
+                    format!("Assignment to undeclared variable '{}'.", name.name.lexeme),
+                ));
+            }
+        }
+
+        for (lint, token, message) in warnings {
+            self.warn(lint, self.span(token), message);
+        }
+    }
+
+    fn span(&self, token: &Token) -> Span {
+        token.span(self.file)
+    }
+}
+
+/// The warning for a declaration that's never read, if it should have one.
+/// Only global functions are checked of the globals, as a script's global
+/// variables may well be meant for whoever runs it.
+fn unused(declaration: &Declaration) -> Option<(Lint, &Token, String)> {
+    let name = &declaration.name.lexeme;
+    if name.starts_with('_') {
+        return None;
+    }
+
+    let (lint, message) = match declaration.kind {
+        DeclarationKind::Function => (
+            Lint::UnusedFunction,
+            format!("Function '{}' is never used.", name),
+        ),
+        _ if declaration.global => return None,
+        DeclarationKind::Variable => (
+            Lint::UnusedVariable,
+            format!("Local variable '{}' is never used.", name),
+        ),
+        DeclarationKind::Class => (
+            Lint::UnusedVariable,
+            format!("Local class '{}' is never used.", name),
+        ),
+        DeclarationKind::Parameter => (
+            Lint::UnusedParameter,
+            format!("Parameter '{}' is never used.", name),
+        ),
+        DeclarationKind::Method | DeclarationKind::Import => return None,
+    };
+
+    Some((lint, &declaration.name, message))
+}
+
+/// What type of value a literal expression has, for describing it.
+fn literal_type(expr: &Expr) -> Option<&'static str> {
+    match &expr.kind {
+        ExprKind::Grouping(expr) => literal_type(expr),
+        ExprKind::Literal(Literal::Number(_)) => Some("a number"),
+        ExprKind::Literal(Literal::String(_)) => Some("a string"),
+        ExprKind::Literal(Literal::True | Literal::False) => Some("a boolean"),
+        ExprKind::Literal(Literal::Nil | Literal::None) => Some("nil"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(source: &str) -> Vec<(Lint, usize, String)> {
+        lint_source(source, None, &Lint::ALL)
+            .unwrap()
+            .into_iter()
+            .map(|warning| (warning.lint, warning.span.start_line, warning.message))
+            .collect()
+    }
+
+    fn lints(source: &str) -> Vec<Lint> {
+        warnings(source)
+            .into_iter()
+            .map(|(lint, ..)| lint)
+            .collect()
+    }
+
+    #[test]
+    fn clean_program() {
+        let source = "
+            var total = 0;
+            fun add(n) { total = total + n; return total; }
+            class Counter { init() { this.count = 0; } }
+            print add(1);
+            print Counter;
+        ";
+        assert_eq!(warnings(source), vec![]);
+    }
+
+    #[test]
+    fn unused_locals_and_parameters() {
+        assert_eq!(
+            warnings("fun f(a, _b) {\n  var c = 1;\n  var d;\n  d = 2;\n}\nf(1, 2);"),
+            vec![
+                (
+                    Lint::UnusedParameter,
+                    1,
+                    "Parameter 'a' is never used.".into()
+                ),
+                (
+                    Lint::UnusedVariable,
+                    2,
+                    "Local variable 'c' is never used.".into()
+                ),
+                (
+                    Lint::UnusedVariable,
+                    3,
+                    "Local variable 'd' is never used.".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_functions() {
+        assert_eq!(
+            lints("fun used() {}\nfun unused() {}\nused();\n{ fun local() {} }"),
+            vec![Lint::UnusedFunction, Lint::UnusedFunction]
+        );
+    }
+
+    #[test]
+    fn functions_used_before_they_are_declared() {
+        assert_eq!(lints("fun a() { b(); }\nfun b() {}\na();"), vec![]);
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            warnings("fun f(a) {\n  {\n    var a = 1;\n    print a;\n  }\n}\nf(1);"),
+            vec![
+                (
+                    Lint::UnusedParameter,
+                    1,
+                    "Parameter 'a' is never used.".into()
+                ),
+                (
+                    Lint::Shadowing,
+                    3,
+                    "'a' shadows a declaration on line 1.".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_code() {
+        assert_eq!(
+            warnings("fun f() {\n  return 1;\n  print 2;\n  print 3;\n}\nf();"),
+            vec![(
+                Lint::UnreachableCode,
+                3,
+                "Unreachable code after 'return'.".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn undeclared_assignment() {
+        assert_eq!(
+            warnings("fun f() { later = 1; missing = 2; }\nvar later;\nf();"),
+            vec![(
+                Lint::UndeclaredAssignment,
+                1,
+                "Assignment to undeclared variable 'missing'.".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn captured_this() {
+        assert_eq!(
+            lints(
+                "class A {\n  m() {\n    fun f() { return this; }\n    return f;\n  }\n}\nprint A;"
+            ),
+            vec![Lint::CapturedThis]
+        );
+    }
+
+    #[test]
+    fn mixed_comparison() {
+        assert_eq!(
+            warnings("print 1 == \"1\";\nprint (nil) < 2;\nprint 1 == 2;"),
+            vec![
+                (
+                    Lint::MixedComparison,
+                    1,
+                    "Comparing a number with a string is always false.".into()
+                ),
+                (
+                    Lint::MixedComparison,
+                    2,
+                    "Comparing nil with a number fails at runtime.".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_block() {
+        assert_eq!(lints("while (false) {}"), vec![Lint::EmptyBlock]);
+        assert_eq!(lints("fun f() {}\nf();"), vec![]);
+    }
+
+    #[test]
+    fn lints_can_be_disabled() {
+        let source = "while (false) {}\nprint 1 == nil;";
+        let warnings = lint_source(source, None, &[Lint::MixedComparison]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::MixedComparison);
+    }
+
+    #[test]
+    fn lint_names() {
+        for lint in Lint::ALL {
+            assert_eq!(lint.name().parse(), Ok(lint));
+        }
+        assert!("no-such-lint".parse::<Lint>().is_err());
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::coverage::Coverage;
+use crate::debugger::Debugger;
+use crate::error::CompileError;
+use crate::interpreter::{Control, Interpreter, RuntimeError};
+use crate::object::Object;
+use crate::parser::{Expr, Parser, Stmt, StmtKind};
+use crate::profiler::Profiler;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::token::{Literal, Token, TokenType};
+
+/// Line used for tokens synthesized by host code, which has no position in
+/// any script.
+pub const HOST_LINE: usize = 0;
+
+/// How much stack a session needs for the deepest recursion scripts are
+/// allowed before they're stopped with a stack overflow error. That's more
+/// than threads get by default, so run sessions on a thread with this much.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// An embeddable Lox session.
+///
+/// Scripts run with [`Lox::run`] share their global scope, so functions and
+/// classes they define can later be looked up and called from Rust. Deep
+/// recursion needs [`STACK_SIZE`] of stack.
+pub struct Lox {
+    resolver: Resolver,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Self {
+            resolver: Resolver::new(Interpreter::new()),
+        }
+    }
+
+    /// Redirects the output of `print` statements.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.resolver.interpreter.set_output(output);
+    }
+
+    /// Redirects where runtime errors are reported.
+    pub fn set_error_output(&mut self, error_output: impl Write + 'static) {
+        self.resolver.interpreter.set_error_output(error_output);
+    }
+
+    /// Replaces the source that scripts read input from.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.resolver.interpreter.set_input(input);
+    }
+
+    /// Sets the arguments scripts see through the `args` native.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.resolver.interpreter.set_args(args);
+    }
+
+    /// Allows or denies scripts the natives that touch files, such as
+    /// `readFile`. They're allowed unless denied, to sandbox scripts that
+    /// aren't trusted.
+    pub fn set_file_access(&mut self, allowed: bool) {
+        self.resolver.interpreter.set_file_access(allowed);
+    }
+
+    /// Sets the file scripts are run from, which their imports are found
+    /// relative to. Otherwise they're found relative to the working
+    /// directory.
+    pub fn set_script_path(&mut self, path: impl Into<PathBuf>) {
+        self.resolver.interpreter.set_script_path(path);
+    }
+
+    /// Limits how many loop iterations and calls scripts may run. `None`
+    /// allows unlimited steps.
+    pub fn set_step_budget(&mut self, step_budget: Option<usize>) {
+        self.resolver.interpreter.set_step_budget(step_budget);
+    }
+
+    /// Attaches a debugger, which pauses scripts at breakpoints and as they
+    /// are stepped through.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.resolver.interpreter.set_debugger(debugger);
+    }
+
+    /// Attaches a profiler, which times every call and statement run from
+    /// then on.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.resolver.interpreter.set_profiler(profiler);
+    }
+
+    /// Detaches the profiler, with the timings it has taken.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.resolver.interpreter.take_profiler()
+    }
+
+    /// Attaches a coverage recorder, which covers every script run from then
+    /// on.
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.resolver.interpreter.set_coverage(coverage);
+    }
+
+    /// Detaches the coverage recorder, with what it recorded.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.resolver.interpreter.take_coverage()
+    }
+
+    /// Runs `source` in this session, stopping at the first runtime error.
+    /// Any errors are also reported to the error output.
+    pub fn run(&mut self, source: String) -> Result<(), LoxError> {
+        let statements = self
+            .compile(source)
+            .map_err(|errors| self.report_compile_errors(errors))?;
+
+        if let Some(coverage) = self.resolver.interpreter.coverage_mut() {
+            coverage.add_statements(&statements);
+        }
+
+        for statement in statements {
+            match self.resolver.interpreter.evaluate_stmt(&statement) {
+                Ok(()) => {}
+                Err(Control::Error(err)) => {
+                    let _ = writeln!(self.resolver.interpreter.error_output(), "{}", err);
+                    return Err(LoxError::Runtime(err));
+                }
+                // The resolver reports top-level returns, so just stop here
+                Err(Control::Return(_)) => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scans, parses and resolves `source` without running it. Any errors are
+    /// also reported to the error output.
+    pub fn check(&mut self, source: String) -> Result<(), LoxError> {
+        self.compile(source)
+            .map(|_| ())
+            .map_err(|errors| self.report_compile_errors(errors))
+    }
+
+    /// Evaluates `source` as a single expression, such as one entered at the
+    /// REPL, and returns its value. Any errors are also reported to the error
+    /// output.
+    pub fn evaluate(&mut self, source: String) -> Result<Rc<Object>, LoxError> {
+        let expr = self
+            .compile_expression(source)
+            .map_err(|errors| self.report_compile_errors(errors))?;
+
+        self.resolver.interpreter.evaluate(&expr).map_err(|err| {
+            let _ = writeln!(self.resolver.interpreter.error_output(), "{}", err);
+            LoxError::Runtime(err)
+        })
+    }
+
+    fn report_compile_errors(&mut self, errors: Vec<CompileError>) -> LoxError {
+        for error in &errors {
+            let _ = writeln!(self.resolver.interpreter.error_output(), "{}", error);
+        }
+
+        LoxError::Compile(errors)
+    }
+
+    fn compile_expression(&mut self, source: String) -> Result<Expr, Vec<CompileError>> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        let expr = Parser::new(tokens).parse_expression()?;
+
+        // The resolver works on statements, but records what it finds by
+        // expression, so the result still applies to the bare expression
+        let statement = Stmt::new(StmtKind::Expression(expr.clone()), expr.span);
+        self.resolver
+            .resolve_statements(std::slice::from_ref(&statement))?;
+
+        Ok(expr)
+    }
+
+    fn compile(&mut self, source: String) -> Result<Vec<Stmt>, Vec<CompileError>> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        let statements = Parser::new(tokens).parse()?;
+        self.resolver.resolve_statements(&statements)?;
+
+        Ok(statements)
+    }
+
+    /// Looks up a global variable, e.g. a function defined by a script, so
+    /// that it can be held on to and passed to [`Lox::call`] later.
+    pub fn global(&self, name: &str) -> Result<Rc<Object>, RuntimeError> {
+        let name = Token::new(
+            TokenType::Identifier,
+            name.to_string(),
+            Literal::None,
+            HOST_LINE,
+            0,
+        );
+
+        self.resolver.interpreter.global(&name)
+    }
+
+    /// Every global variable, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Rc<Object>)> {
+        self.resolver.interpreter.globals()
+    }
+
+    /// Calls the global function or class called `name`.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        arguments: &[Rc<Object>],
+    ) -> Result<Rc<Object>, RuntimeError> {
+        let callee = self.global(name)?;
+        self.call(&callee, arguments)
+    }
+
+    /// Calls a function or class previously obtained from [`Lox::global`].
+    pub fn call(
+        &mut self,
+        callee: &Object,
+        arguments: &[Rc<Object>],
+    ) -> Result<Rc<Object>, RuntimeError> {
+        let paren = Token::new(
+            TokenType::RightParen,
+            ")".to_string(),
+            Literal::None,
+            HOST_LINE,
+            0,
+        );
+
+        self.resolver
+            .interpreter
+            .call(callee, &paren, arguments.to_vec())
+    }
+}
+
+#[derive(Debug)]
+pub enum LoxError {
+    /// The source failed to scan, parse or resolve.
+    Compile(Vec<CompileError>),
+    Runtime(RuntimeError),
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoxError::Compile(errors) => {
+                let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+            LoxError::Runtime(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_expression() {
+        let mut lox = Lox::new();
+        lox.run("var a = 2;".to_string()).unwrap();
+
+        let value = lox.evaluate("a * 3".to_string()).unwrap();
+        assert_eq!(*value, Object::Number(6.0));
+    }
+
+    #[test]
+    fn call_a_script_function() {
+        let mut lox = Lox::new();
+        lox.run("fun add(a, b) { return a + b; }".to_string())
+            .unwrap();
+
+        let arguments = [Rc::new(Object::Number(1.0)), Rc::new(Object::Number(2.0))];
+        let sum = lox.call_function("add", &arguments).unwrap();
+        assert_eq!(*sum, Object::Number(3.0));
+
+        let add = lox.global("add").unwrap();
+        let sum = lox
+            .call(&add, &[sum, Rc::new(Object::Number(4.0))])
+            .unwrap();
+        assert_eq!(*sum, Object::Number(7.0));
+    }
+
+    #[test]
+    fn call_with_the_wrong_arity() {
+        let mut lox = Lox::new();
+        lox.run("fun add(a, b) { return a + b; }".to_string())
+            .unwrap();
+
+        let err = lox
+            .call_function("add", &[Rc::new(Object::Number(1.0))])
+            .unwrap_err();
+        assert_eq!(err.message(), "Expected 2 arguments but got 1.");
+    }
+
+    #[test]
+    fn call_an_undefined_name() {
+        let mut lox = Lox::new();
+
+        let err = lox.call_function("missing", &[]).unwrap_err();
+        assert_eq!(err.message(), "Undefined variable: 'missing'.");
+    }
+
+    #[test]
+    fn stack_trace_of_a_nested_error() {
+        let mut lox = Lox::new();
+        lox.run(
+            "fun inner() {\n  return 1 + nil;\n}\nfun outer() {\n  return inner();\n}".to_string(),
+        )
+        .unwrap();
+
+        let err = lox.call_function("outer", &[]).unwrap_err();
+        assert_eq!(
+            err.stack_trace(),
+            "Operands must be two numbers or two strings.\n\
+             [line 2] in inner()\n\
+             [line 5] in outer()"
+        );
+    }
+
+    #[test]
+    fn step_budget_stops_infinite_loops() {
+        let mut lox = Lox::new();
+        lox.set_error_output(std::io::sink());
+        lox.set_step_budget(Some(100));
+
+        match lox.run("while (true) {}".to_string()) {
+            Err(LoxError::Runtime(err)) => assert_eq!(err.message(), "Step budget exhausted."),
+            result => panic!("expected the budget to run out, got {:?}", result),
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::callable::{Callable, LoxFunction};
 use crate::interpreter::{Interpreter, RuntimeError};
@@ -9,15 +10,26 @@ use crate::lox_instance::LoxInstance;
 use crate::object::Object;
 use crate::token::Token;
 
+/// The id of the next class to be declared.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug, Clone)]
 pub struct LoxClass {
     pub name: String,
     pub methods: HashMap<String, LoxFunction>,
+    id: usize, // shared by its copies, as instances hold one each
 }
 
 impl LoxClass {
     pub fn new(name: String, methods: HashMap<String, LoxFunction>) -> Self {
-        Self { name, methods }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        Self { name, methods, id }
+    }
+
+    /// Whether `other` is a copy of this class, rather than another class,
+    /// even one with the same name.
+    pub fn is(&self, other: &LoxClass) -> bool {
+        self.id == other.id
     }
 
     pub fn find_method(&self, name: &str) -> Option<&LoxFunction> {
@@ -39,15 +51,18 @@ impl Callable for LoxClass {
         arguments: Vec<Rc<Object>>,
     ) -> Result<Rc<Object>, RuntimeError> {
         let instance = LoxInstance::new(self.clone());
+        let instance = Rc::new(Object::Instance(RefCell::new(instance)));
 
-        let initializer = self.find_method("init").cloned();
-        if let Some(initializer) = initializer {
-            let _ = initializer
-                .bind(&instance)
-                .call(interpreter, paren, arguments);
+        match self.find_method("init") {
+            Some(initializer) => {
+                initializer
+                    .bind(instance.clone())
+                    .call(interpreter, paren, arguments)?;
+            }
+            None => self.check_arity(paren, &arguments)?,
         }
 
-        Ok(Rc::new(Object::Instance(RefCell::new(instance))))
+        Ok(instance)
     }
 
     fn arity(&self) -> usize {
@@ -20,17 +20,52 @@ impl LoxInstance {
         }
     }
 
-    pub fn get(&self, name: &Token) -> Option<Rc<Object>> {
+    /// Looks up a field or method. `this` is the object wrapping this
+    /// instance, which methods are bound to.
+    pub fn get(&self, name: &Token, this: &Rc<Object>) -> Option<Rc<Object>> {
         self.fields.get(&name.lexeme).cloned().or_else(|| {
             // If no field found, check for a method on the class
             self.klass
                 .find_method(&name.lexeme)
-                .cloned()
-                .map(|method| method.bind(self))
+                .map(|method| method.bind(this.clone()))
                 .map(|method| Rc::new(Object::Callable(Box::new(method))))
         })
     }
 
+    pub fn class(&self) -> &LoxClass {
+        &self.klass
+    }
+
+    pub fn field(&self, name: &str) -> Option<Rc<Object>> {
+        self.fields.get(name).cloned()
+    }
+
+    /// Every field and its value, sorted by name.
+    pub fn fields(&self) -> Vec<(String, Rc<Object>)> {
+        let mut fields: Vec<_> = self
+            .fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        fields
+    }
+
+    /// The names of every field and method, sorted.
+    pub fn property_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .fields
+            .keys()
+            .chain(self.klass.methods.keys())
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+
+        names
+    }
+
     pub fn set(&mut self, name: &Token, value: Rc<Object>) {
         self.fields.insert(name.lexeme.clone(), value);
     }
@@ -0,0 +1,502 @@
+//! A language server speaking the Language Server Protocol over standard
+//! input and output.
+//!
+//! Documents are analyzed in full on every change. Completion and the outline
+//! use the last analysis that compiled, so they keep working while an edit is
+//! half-typed. Navigation needs the analysis to match the text positions are
+//! given in, so it waits until the document compiles again.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Request as _,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionParams, Diagnostic, DiagnosticSeverity,
+    DocumentSymbol, DocumentSymbolParams, GotoDefinitionParams, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ReferenceParams, ServerCapabilities, SymbolKind,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde_json::Value;
+
+use jlox::analysis::{self, Analysis, DefinitionKind};
+use jlox::error::CompileError;
+use jlox::lint::Warning;
+use jlox::natives;
+use jlox::scanner::KEYWORDS;
+use jlox::token::Token;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Runs the server until the client asks it to exit.
+pub fn run_server() {
+    if let Err(err) = serve() {
+        eprintln!("Language server error: {}", err);
+        std::process::exit(74);
+    }
+}
+
+fn serve() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(Default::default()),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                connection.sender.send(server.request(request).into())?;
+            }
+            Message::Notification(notification) => {
+                for diagnostics in server.notification(notification)? {
+                    connection.sender.send(diagnostics.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    // The writer thread only finishes once the connection is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis, // the last analysis that compiled
+    analyzed: String,   // the text that analysis is of
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    fn request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                params::<GotoDefinition>(request).map(|params| self.definition(params))
+            }
+            References::METHOD => {
+                params::<References>(request).map(|params| self.references(params))
+            }
+            HoverRequest::METHOD => {
+                params::<HoverRequest>(request).map(|params| self.hover(params))
+            }
+            DocumentSymbolRequest::METHOD => {
+                params::<DocumentSymbolRequest>(request).map(|params| self.document_symbols(params))
+            }
+            Completion::METHOD => {
+                params::<Completion>(request).map(|params| self.completion(params))
+            }
+            method => {
+                let message = format!("Unsupported request '{}'.", method);
+                return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+            }
+        };
+
+        match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    /// Updates the open documents, returning the diagnostics to publish.
+    fn notification(&mut self, notification: Notification) -> Result<Vec<Notification>> {
+        let (uri, text) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = notification_params::<DidOpenTextDocument>(notification)?;
+                (params.text_document.uri, Some(params.text_document.text))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = notification_params::<DidChangeTextDocument>(notification)?;
+                // With full sync, the last change holds the whole document
+                let text = params
+                    .content_changes
+                    .into_iter()
+                    .last()
+                    .map(|change| change.text);
+                (params.text_document.uri, text)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = notification_params::<DidCloseTextDocument>(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                (params.text_document.uri, None)
+            }
+            _ => return Ok(vec![]),
+        };
+
+        let diagnostics = match text {
+            Some(text) => self.update(&uri, text),
+            None => vec![],
+        };
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        Ok(vec![Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )])
+    }
+
+    fn update(&mut self, uri: &Url, text: String) -> Vec<Diagnostic> {
+        let path = uri.to_file_path().ok();
+        let analysis = analysis::analyze(&text, path.as_deref());
+        let diagnostics = diagnostics(&text, &analysis);
+
+        match self.documents.get_mut(uri) {
+            Some(document) => {
+                if analysis.is_valid() {
+                    document.analysis = analysis;
+                    document.analyzed = text.clone();
+                }
+                document.text = text;
+            }
+            None => {
+                let document = Document {
+                    analyzed: text.clone(),
+                    text,
+                    analysis,
+                };
+                self.documents.insert(uri.clone(), document);
+            }
+        }
+
+        diagnostics
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Value {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let location = self
+            .definition_at(&uri, position.position)
+            .map(|(document, index)| {
+                let name = &document.analysis.definitions[index].name;
+                Location::new(uri.clone(), token_range(&document.analyzed, name))
+            });
+
+        serde_json::to_value(location).unwrap_or_default()
+    }
+
+    fn references(&self, params: ReferenceParams) -> Value {
+        let position = params.text_document_position;
+        let uri = position.text_document.uri;
+        let Some((document, index)) = self.definition_at(&uri, position.position) else {
+            return Value::Null;
+        };
+
+        let analysis = &document.analysis;
+        let declaration = params
+            .context
+            .include_declaration
+            .then_some(&analysis.definitions[index].name);
+        let locations: Vec<Location> = declaration
+            .into_iter()
+            .chain(analysis.references_to(index))
+            .map(|token| Location::new(uri.clone(), token_range(&document.analyzed, token)))
+            .collect();
+
+        serde_json::to_value(locations).unwrap_or_default()
+    }
+
+    fn hover(&self, params: HoverParams) -> Value {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let hover = self
+            .definition_at(&uri, position.position)
+            .map(|(document, index)| {
+                let definition = &document.analysis.definitions[index];
+                Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: format!("```lox\n{}\n```", definition.signature),
+                    }),
+                    range: None,
+                }
+            });
+
+        serde_json::to_value(hover).unwrap_or_default()
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Value {
+        let symbols = self
+            .documents
+            .get(&params.text_document.uri)
+            .map(|document| symbols(document, None))
+            .unwrap_or_default();
+
+        serde_json::to_value(symbols).unwrap_or_default()
+    }
+
+    fn completion(&self, params: CompletionParams) -> Value {
+        let uri = params.text_document_position.text_document.uri;
+        let mut items: Vec<CompletionItem> = KEYWORDS
+            .iter()
+            .map(|(keyword, _)| completion_item(keyword, CompletionItemKind::KEYWORD, None))
+            .collect();
+
+        for name in natives::NAMES {
+            items.push(completion_item(name, CompletionItemKind::FUNCTION, None));
+        }
+
+        if let Some(document) = self.documents.get(&uri) {
+            for definition in &document.analysis.definitions {
+                let kind = match definition.kind {
+                    DefinitionKind::Variable => CompletionItemKind::VARIABLE,
+                    DefinitionKind::Parameter => CompletionItemKind::VARIABLE,
+                    DefinitionKind::Function => CompletionItemKind::FUNCTION,
+                    DefinitionKind::Class => CompletionItemKind::CLASS,
+                    DefinitionKind::Method => CompletionItemKind::METHOD,
+                };
+                let signature = Some(definition.signature.clone());
+                items.push(completion_item(&definition.name.lexeme, kind, signature));
+            }
+        }
+
+        // The same name may be declared in several scopes
+        items.sort_by(|a, b| a.label.cmp(&b.label));
+        items.dedup_by(|a, b| a.label == b.label);
+
+        serde_json::to_value(items).unwrap_or_default()
+    }
+
+    /// The document at `uri` and the definition of the name at `position`,
+    /// unless the document has changed since it last compiled.
+    fn definition_at(&self, uri: &Url, position: Position) -> Option<(&Document, usize)> {
+        let document = self.documents.get(uri)?;
+        if document.text != document.analyzed {
+            return None;
+        }
+        let (line, column) = from_position(&document.text, position);
+        let index = document.analysis.definition_at(line, column)?;
+
+        Some((document, index))
+    }
+}
+
+fn params<R: lsp_types::request::Request>(request: Request) -> Result<R::Params> {
+    Ok(serde_json::from_value(request.params)?)
+}
+
+fn notification_params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Result<N::Params> {
+    Ok(serde_json::from_value(notification.params)?)
+}
+
+fn diagnostics(text: &str, analysis: &Analysis) -> Vec<Diagnostic> {
+    let errors = analysis
+        .errors
+        .iter()
+        .map(|error| error_diagnostic(text, error));
+    let warnings = analysis
+        .warnings
+        .iter()
+        .map(|warning| warning_diagnostic(text, warning));
+
+    errors.chain(warnings).collect()
+}
+
+fn error_diagnostic(text: &str, error: &CompileError) -> Diagnostic {
+    let range = if error.column == 0 {
+        // Only the line is known, so flag all of it
+        let line_text = text.lines().nth(error.line.saturating_sub(1)).unwrap_or("");
+        Range::new(
+            to_position(text, error.line, 1),
+            to_position(text, error.line, line_text.chars().count() + 1),
+        )
+    } else {
+        Range::new(
+            to_position(text, error.line, error.column),
+            to_position(text, error.line, error.column + error.length.max(1)),
+        )
+    };
+
+    diagnostic(range, DiagnosticSeverity::ERROR, error.message.clone())
+}
+
+fn warning_diagnostic(text: &str, warning: &Warning) -> Diagnostic {
+    let span = &warning.span;
+    let range = Range::new(
+        to_position(text, span.start_line, span.start_column),
+        to_position(text, span.end_line, span.end_column),
+    );
+    let message = format!("{} [{}]", warning.message, warning.lint);
+
+    diagnostic(range, DiagnosticSeverity::WARNING, message)
+}
+
+fn diagnostic(range: Range, severity: DiagnosticSeverity, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        source: Some("jlox".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// The classes, functions and methods declared directly inside `parent`.
+fn symbols(document: &Document, parent: Option<usize>) -> Vec<DocumentSymbol> {
+    let analysis = &document.analysis;
+
+    analysis
+        .children(parent)
+        .into_iter()
+        .filter_map(|index| {
+            let definition = &analysis.definitions[index];
+            let kind = match definition.kind {
+                DefinitionKind::Class => SymbolKind::CLASS,
+                DefinitionKind::Function => SymbolKind::FUNCTION,
+                DefinitionKind::Method => SymbolKind::METHOD,
+                DefinitionKind::Variable | DefinitionKind::Parameter => return None,
+            };
+            let range = token_range(&document.analyzed, &definition.name);
+
+            #[allow(deprecated)] // `deprecated` has to be given, though it's unused
+            Some(DocumentSymbol {
+                name: definition.name.lexeme.clone(),
+                detail: Some(definition.signature.clone()),
+                kind,
+                tags: None,
+                deprecated: None,
+                range,
+                selection_range: range,
+                children: Some(symbols(document, Some(index))),
+            })
+        })
+        .collect()
+}
+
+fn completion_item(
+    label: &str,
+    kind: CompletionItemKind,
+    detail: Option<String>,
+) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        detail,
+        ..Default::default()
+    }
+}
+
+fn token_range(text: &str, token: &Token) -> Range {
+    // Only the first line of a multi-line string is covered
+    let length = token.lexeme.lines().next().unwrap_or("").chars().count();
+    Range::new(
+        to_position(text, token.line, token.column),
+        to_position(text, token.line, token.column + length),
+    )
+}
+
+/// Converts a 1-based line and character column to a protocol position,
+/// which is 0-based and counts UTF-16 code units.
+fn to_position(text: &str, line: usize, column: usize) -> Position {
+    let line_text = text.lines().nth(line.saturating_sub(1)).unwrap_or("");
+    let character: usize = line_text
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(char::len_utf16)
+        .sum();
+
+    Position::new(line.saturating_sub(1) as u32, character as u32)
+}
+
+/// Converts a protocol position back to a 1-based line and character column.
+fn from_position(text: &str, position: Position) -> (usize, usize) {
+    let line_text = text.lines().nth(position.line as usize).unwrap_or("");
+    let mut units = 0;
+    let column = line_text
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= position.character as usize
+        })
+        .count();
+
+    (position.line as usize + 1, column + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{TextDocumentIdentifier, TextDocumentPositionParams};
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let text = "print \"é😀\";\nvar x;";
+
+        // The emoji before `;` takes two code units
+        assert_eq!(to_position(text, 1, 11), Position::new(0, 11));
+        assert_eq!(from_position(text, Position::new(0, 11)), (1, 11));
+        assert_eq!(to_position(text, 2, 5), Position::new(1, 4));
+        assert_eq!(from_position(text, Position::new(1, 4)), (2, 5));
+    }
+
+    #[test]
+    fn diagnostics_for_errors_and_warnings() {
+        let text = "print ;";
+        let errors = diagnostics(text, &analysis::analyze(text, None));
+        assert_eq!(errors[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(errors[0].range.start, Position::new(0, 6));
+
+        let text = "{ var unused; }";
+        let warnings = diagnostics(text, &analysis::analyze(text, None));
+        assert_eq!(warnings[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            warnings[0].range,
+            Range::new(Position::new(0, 6), Position::new(0, 12))
+        );
+    }
+
+    fn definition(server: &Server, uri: &Url, line: u32, character: u32) -> Value {
+        let position = Position::new(line, character);
+        server.definition(GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                position,
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+    }
+
+    #[test]
+    fn navigation_waits_for_the_document_to_compile() {
+        let mut server = Server::default();
+        let uri = Url::parse("file:///tmp/a.lox").unwrap();
+        let declared_on = |location: Value| location["range"]["start"]["line"].as_u64();
+
+        server.update(&uri, "var a = 1;\nprint a;".to_string());
+        assert_eq!(declared_on(definition(&server, &uri, 1, 6)), Some(0));
+
+        // A line above moves everything down, but the analysis still has
+        // the old positions
+        server.update(&uri, "print (;\nvar a = 1;\nprint a;".to_string());
+        assert_eq!(definition(&server, &uri, 2, 6), Value::Null);
+
+        server.update(&uri, "print 0;\nvar a = 1;\nprint a;".to_string());
+        assert_eq!(declared_on(definition(&server, &uri, 2, 6)), Some(1));
+    }
+}
@@ -1,91 +1,457 @@
-use std::io::{BufRead, Write};
-
-mod callable;
-mod environment;
-mod interpreter;
-mod lox_class;
-mod lox_instance;
-mod object;
-mod parser;
-mod resolver;
-mod scanner;
-mod token;
-
-use interpreter::Interpreter;
-use parser::Parser;
-use resolver::Resolver;
-use scanner::Scanner;
-
-trait Visitor<R> {}
+use std::io::Read;
+use std::path::Path;
+
+use clap::{Parser, Subcommand};
+
+use jlox::coverage::Coverage;
+use jlox::error::CompileError;
+use jlox::formatter;
+use jlox::lint::{self, Lint, Warning};
+use jlox::lox::{Lox, LoxError, STACK_SIZE};
+use jlox::profiler::Profiler;
+
+mod dap;
+mod debug;
+mod dump;
+mod lsp;
+mod repl;
+
+use dump::{dump_ast, dump_tokens, Format};
+use lsp::run_server;
+use repl::run_prompt;
+
+/// A tree-walking interpreter for Lox.
+///
+/// With no arguments, starts an interactive session.
+#[derive(Parser)]
+#[command(name = "jlox", version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Run CODE instead of a script; any other arguments are passed to it
+    #[arg(short = 'e', long = "eval", value_name = "CODE")]
+    eval: Option<String>,
+
+    /// Print the tokens of the script instead of running it
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        requires = "script",
+        conflicts_with = "dump_ast"
+    )]
+    dump_tokens: Option<Format>,
+
+    /// Print the syntax tree of the script instead of running it
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        requires = "script"
+    )]
+    dump_ast: Option<Format>,
+
+    /// Time the script, printing a report and writing folded stacks for
+    /// flame graphs to FILE
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "jlox.folded",
+        conflicts_with_all = ["dump_tokens", "dump_ast"]
+    )]
+    profile: Option<String>,
+
+    /// Record which lines and branches of the script run, printing a summary
+    /// and writing an lcov report to FILE
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "lcov.info",
+        requires = "script",
+        conflicts_with_all = ["eval", "dump_tokens", "dump_ast", "profile"]
+    )]
+    coverage: Option<String>,
+
+    /// Deny scripts the natives that touch files, such as `readFile`, and imports
+    #[arg(long)]
+    sandbox: bool,
+
+    /// Script to run, or `-` to read it from standard input
+    script: Option<String>,
+
+    /// Arguments passed to the script, available through `args()`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a script
+    Run {
+        /// Deny the script the natives that touch files, such as `readFile`,
+        /// and imports
+        #[arg(long)]
+        sandbox: bool,
+
+        /// Script to run, or `-` to read it from standard input
+        script: String,
+
+        /// Arguments passed to the script, available through `args()`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run a script under an interactive debugger
+    Debug {
+        /// Pause at LINE instead of at the start; may be given more than once
+        #[arg(short, long = "break", value_name = "LINE")]
+        breakpoints: Vec<usize>,
+
+        /// Deny the script the natives that touch files, such as `readFile`,
+        /// and imports
+        #[arg(long)]
+        sandbox: bool,
+
+        /// Script to debug
+        script: String,
+
+        /// Arguments passed to the script, available through `args()`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Scan, parse and resolve a script without running it
+    Check {
+        /// Deny the script the natives that touch files, such as `readFile`,
+        /// and imports
+        #[arg(long)]
+        sandbox: bool,
+
+        /// Script to check, or `-` to read it from standard input
+        script: String,
+    },
+    /// Format scripts in place, or `-` from standard input to standard output
+    Fmt {
+        /// Only report which scripts aren't formatted, failing if any aren't
+        #[arg(long)]
+        check: bool,
+
+        #[arg(required = true)]
+        scripts: Vec<String>,
+    },
+    /// Warn about likely mistakes in scripts
+    Lint {
+        /// Don't warn about LINT; may be given more than once
+        #[arg(long, value_name = "LINT", value_parser = parse_lint, long_help = allow_help())]
+        allow: Vec<Lint>,
+
+        #[arg(required = true)]
+        scripts: Vec<String>,
+    },
+    /// Run a language server over standard input and output
+    Lsp,
+    /// Run a Debug Adapter Protocol server over standard input and output
+    Dap {
+        /// Deny the script the natives that touch files, such as `readFile`,
+        /// and imports
+        #[arg(long)]
+        sandbox: bool,
+    },
+}
+
+fn parse_lint(name: &str) -> Result<Lint, String> {
+    name.parse()
+}
+
+fn allow_help() -> String {
+    let names: Vec<&str> = Lint::ALL.iter().map(Lint::name).collect();
+    format!(
+        "Don't warn about LINT; may be given more than once\n\nLints: {}",
+        names.join(", ")
+    )
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().skip(1).collect();
-
-    match args.len() {
-        0 => run_prompt(),
-        1 => run_file(args.first().unwrap()),
-        _ => {
-            eprintln!("Usage: jlox [script]");
-            std::process::exit(64);
+    // Scripts recurse on the interpreter's stack, so give it enough for them
+    let main = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_cli)
+        .unwrap_or_else(|err| {
+            eprintln!("Error starting interpreter: {}", err);
+            std::process::exit(70);
+        });
+
+    // A panic has already been reported by the hook
+    if main.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+fn run_cli() {
+    let cli = Cli::try_parse().unwrap_or_else(|err| {
+        let _ = err.print();
+        // --help and --version are reported as errors too
+        std::process::exit(if err.use_stderr() { 64 } else { 0 });
+    });
+
+    match cli.command {
+        Some(Command::Run {
+            sandbox,
+            script,
+            args,
+        }) => run_in(session(&script, sandbox), read_source(&script), args),
+        Some(Command::Debug {
+            breakpoints,
+            sandbox,
+            script,
+            args,
+        }) => {
+            let source = read_source(&script);
+            let mut lox = session(&script, sandbox);
+            lox.set_debugger(debug::debugger(&source, breakpoints));
+            run_in(lox, source, args);
         }
+        Some(Command::Check { sandbox, script }) => {
+            check(session(&script, sandbox), read_source(&script))
+        }
+        Some(Command::Fmt { check, scripts }) => format_scripts(&scripts, check),
+        Some(Command::Lint { allow, scripts }) => lint_scripts(&scripts, &allow),
+        Some(Command::Lsp) => run_server(),
+        Some(Command::Dap { sandbox }) => dap::run_adapter(sandbox),
+        None => match (cli.eval, cli.script) {
+            (Some(code), script) => {
+                let args = script.into_iter().chain(cli.args).collect();
+                let lox = session("-", cli.sandbox);
+                match cli.profile {
+                    Some(path) => profile(lox, code, args, &path),
+                    None => run_in(lox, code, args),
+                }
+            }
+            (None, Some(script)) => {
+                let source = read_source(&script);
+                let lox = session(&script, cli.sandbox);
+                match (cli.dump_tokens, cli.dump_ast, cli.profile, cli.coverage) {
+                    (Some(format), ..) => dump(source, dump_tokens, format),
+                    (_, Some(format), ..) => dump(source, dump_ast, format),
+                    (_, _, Some(path), _) => profile(lox, source, cli.args, &path),
+                    (_, _, _, Some(path)) => cover(lox, source, &script, cli.args, &path),
+                    _ => run_in(lox, source, cli.args),
+                }
+            }
+            (None, None) => run_prompt(cli.sandbox),
+        },
+    }
+}
+
+/// Reads a script from a file, or from standard input if `path` is `-`.
+fn read_source(path: &str) -> String {
+    let mut source = String::new();
+    let result = if path == "-" {
+        std::io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        std::fs::read_to_string(path)
     };
+
+    result.unwrap_or_else(|err| {
+        eprintln!("Error reading '{}': {}", path, err);
+        std::process::exit(66);
+    })
 }
 
-fn run_prompt() {
-    let stdin = std::io::stdin();
-    let mut stdin = stdin.lock();
-    let mut line = String::with_capacity(100);
-    let interpreter = Interpreter::new();
-    let mut resolver = Resolver::new(interpreter);
-    print_prompt();
+/// A session for running the script at `path`, so its imports are found
+/// relative to it, or `-` for a script that isn't in a file. If `sandbox` is
+/// set, it can't touch files.
+fn session(path: &str, sandbox: bool) -> Lox {
+    let mut lox = Lox::new();
+    if path != "-" {
+        lox.set_script_path(path);
+    }
+    lox.set_file_access(!sandbox);
+    lox
+}
 
-    while stdin.read_line(&mut line).is_ok() {
-        let trimmed = String::from(line.trim());
-        let _ = run(&mut resolver, trimmed);
-        // run(line.clone());
+fn run_in(mut lox: Lox, program: String, args: Vec<String>) {
+    lox.set_args(args);
+    exit_on_error(lox.run(program));
+}
+
+/// Runs a script under the profiler, then reports where it spent its time
+/// and writes the folded stacks to `path`.
+fn profile(mut lox: Lox, program: String, args: Vec<String>, path: &str) {
+    lox.set_args(args);
+    lox.set_profiler(Profiler::new());
+    let result = lox.run(program);
 
-        line.clear();
-        print_prompt();
+    if let Some(profiler) = lox.take_profiler() {
+        eprint!("{}", profiler.report());
+        if let Err(err) = std::fs::write(path, profiler.folded_stacks()) {
+            eprintln!("Error writing '{}': {}", path, err);
+            std::process::exit(73);
+        }
     }
+
+    exit_on_error(result);
 }
 
-fn print_prompt() {
-    print!("> ");
-    std::io::stdout().flush().expect("error flushing stdout");
+/// Runs a script, recording which lines and branches run, then summarizes
+/// them and writes an lcov report of the script at `script` to `path`.
+fn cover(mut lox: Lox, program: String, script: &str, args: Vec<String>, path: &str) {
+    lox.set_args(args);
+    lox.set_coverage(Coverage::new());
+    let result = lox.run(program);
+
+    if let Some(coverage) = lox.take_coverage() {
+        eprint!("{}", coverage.summary());
+        if let Err(err) = std::fs::write(path, coverage.lcov(script)) {
+            eprintln!("Error writing '{}': {}", path, err);
+            std::process::exit(73);
+        }
+    }
+
+    exit_on_error(result);
 }
 
-fn run(resolver: &mut Resolver, source: String) -> Result<(), ()> {
-    let mut scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens().expect("scan error");
+fn exit_on_error(result: Result<(), LoxError>) {
+    match result {
+        Ok(()) => {}
+        Err(LoxError::Compile(_)) => std::process::exit(65),
+        Err(LoxError::Runtime(_)) => std::process::exit(70),
+    }
+}
 
-    // println!();
-    // for token in &tokens {
-    //     println!("{:?}", token);
-    // }
+fn check(mut lox: Lox, program: String) {
+    if lox.check(program).is_err() {
+        std::process::exit(65);
+    }
+}
 
-    let mut parser = Parser::new(tokens);
-    let statements = parser.parse();
+/// Formats every script, exiting with 65 if any fail to parse, or with 1 if
+/// `check` is set and any aren't formatted.
+fn format_scripts(paths: &[String], check: bool) {
+    let mut failed = false;
+    let mut unformatted = false;
 
-    // println!();
-    // dbg!(&expr);
+    for path in paths {
+        let source = read_source(path);
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                eprintln!("Error formatting '{}':", path);
+                report_errors(&errors);
+                failed = true;
+                continue;
+            }
+        };
 
-    let statements = statements.ok_or(())?;
-    resolver.resolve_statements(&statements);
+        if check {
+            if formatted != source {
+                println!("{} is not formatted", path);
+                unformatted = true;
+            }
+        } else if path == "-" {
+            print!("{}", formatted);
+        } else if formatted != source {
+            if let Err(err) = std::fs::write(path, formatted) {
+                eprintln!("Error writing '{}': {}", path, err);
+                std::process::exit(73);
+            }
+        }
+    }
 
-    for statement in statements {
-        if let Err(err) = resolver.interpreter.evaluate_stmt(&statement) {
-            eprintln!("{}", err);
+    if failed {
+        std::process::exit(65);
+    } else if unformatted {
+        std::process::exit(1);
+    }
+}
+
+/// Lints every script, exiting with 65 if any fail to compile, or with 1 if
+/// there were any warnings.
+fn lint_scripts(paths: &[String], allow: &[Lint]) {
+    let lints: Vec<Lint> = Lint::ALL
+        .into_iter()
+        .filter(|lint| !allow.contains(lint))
+        .collect();
+    let mut failed = false;
+    let mut warned = false;
+
+    for path in paths {
+        let source = read_source(path);
+        let script = (path != "-").then_some(Path::new(path));
+        match lint::lint_source(&source, script, &lints) {
+            Ok(warnings) => {
+                for warning in &warnings {
+                    print_warning(path, &source, warning);
+                }
+                warned |= !warnings.is_empty();
+            }
+            Err(errors) => {
+                report_errors(&errors);
+                failed = true;
+            }
         }
     }
 
-    Ok(())
+    if failed {
+        std::process::exit(65);
+    } else if warned {
+        std::process::exit(1);
+    }
 }
 
-fn run_file(filename: &str) {
-    let program = std::fs::read_to_string(filename).expect("error reading file");
-    let interpreter = Interpreter::new();
-    let mut resolver = Resolver::new(interpreter);
-    if run(&mut resolver, program).is_err() {
+/// Prints a warning along with the line it starts on, underlining the
+/// code it's about as far as the end of that line.
+fn print_warning(path: &str, source: &str, warning: &Warning) {
+    let span = &warning.span;
+    eprintln!(
+        "{}:{}:{}: warning: {} [{}]",
+        path, span.start_line, span.start_column, warning.message, warning.lint
+    );
+
+    if let Some(line) = source.lines().nth(span.start_line.saturating_sub(1)) {
+        let number = span.start_line.to_string();
+        let end = if span.end_line == span.start_line {
+            span.end_column
+        } else {
+            line.chars().count() + 1
+        };
+        let width = end.saturating_sub(span.start_column);
+        let offset: String = line
+            .chars()
+            .take(span.start_column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        eprintln!("{} | {}", number, line);
+        eprintln!(
+            "{} | {}{}",
+            " ".repeat(number.len()),
+            offset,
+            "^".repeat(width.max(1))
+        );
+    }
+}
+
+fn dump(
+    program: String,
+    dump: fn(String, Format) -> Result<(), Vec<CompileError>>,
+    format: Format,
+) {
+    if let Err(errors) = dump(program, format) {
+        report_errors(&errors);
         std::process::exit(65);
     }
 }
+
+fn report_errors(errors: &[CompileError]) {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+}
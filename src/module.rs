@@ -0,0 +1,259 @@
+//! Modules, the other files a script imports.
+//!
+//! The resolver compiles a file the first time it's imported, giving it a
+//! file id of its own for the spans in it, and the interpreter runs it the
+//! first time one of its imports runs. Each module has its own global scope,
+//! which starts out with just the natives in it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::parser::{Stmt, StmtKind};
+use crate::token::{Span, MAIN_FILE};
+
+pub struct Module {
+    pub name: String,       // the path as it was first imported
+    pub names: Vec<String>, // declared at its top level
+    path: PathBuf,          // canonical, to tell whether it's already loaded
+    failed: bool,           // to compile, so it's loaded afresh next time
+    pub(crate) statements: Rc<[Stmt]>,
+    pub(crate) environment: Option<Rc<RefCell<Environment>>>, // once it has run
+}
+
+#[derive(Default)]
+pub struct Modules {
+    script: Option<PathBuf>,       // the file scripts are run from, if any
+    modules: Vec<Module>,          // by file id, from the one after MAIN_FILE
+    loading: Vec<usize>,           // files being compiled, outermost first
+    imports: HashMap<Span, usize>, // the file each import statement loads
+}
+
+impl Modules {
+    /// Sets the file that scripts are run from, which imports in them are
+    /// relative to. Otherwise they're relative to the working directory.
+    pub fn set_script(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.script = Some(path.canonicalize().unwrap_or(path));
+    }
+
+    pub fn get(&self, file: usize) -> &Module {
+        &self.modules[file - MAIN_FILE - 1]
+    }
+
+    pub(crate) fn get_mut(&mut self, file: usize) -> &mut Module {
+        &mut self.modules[file - MAIN_FILE - 1]
+    }
+
+    /// Where `path`, as imported from `file`, points.
+    pub(crate) fn locate(&self, file: usize, path: &str) -> PathBuf {
+        let importer = if file == MAIN_FILE {
+            self.script.as_deref()
+        } else {
+            Some(self.get(file).path.as_path())
+        };
+
+        match importer.and_then(Path::parent) {
+            Some(directory) => directory.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    /// The file already loaded from the canonical `path`, if any.
+    pub(crate) fn find(&self, path: &Path) -> Option<usize> {
+        if self.script.as_deref() == Some(path) {
+            return Some(MAIN_FILE);
+        }
+
+        self.modules
+            .iter()
+            .position(|module| !module.failed && module.path == path)
+            .map(|index| index + MAIN_FILE + 1)
+    }
+
+    /// The chain of imports that leads back to `file`, if it's still being
+    /// compiled, e.g. `a.lox -> b.lox -> a.lox`.
+    pub(crate) fn cycle(&self, file: usize) -> Option<String> {
+        let compiling: Vec<usize> = std::iter::once(MAIN_FILE)
+            .chain(self.loading.iter().copied())
+            .collect();
+        let start = compiling.iter().position(|&loading| loading == file)?;
+
+        let names: Vec<&str> = compiling[start..]
+            .iter()
+            .chain(std::iter::once(&file))
+            .map(|&file| self.name(file))
+            .collect();
+        Some(names.join(" -> "))
+    }
+
+    fn name(&self, file: usize) -> &str {
+        if file != MAIN_FILE {
+            &self.get(file).name
+        } else {
+            self.script
+                .as_deref()
+                .and_then(Path::file_name)
+                .and_then(|name| name.to_str())
+                .unwrap_or("script")
+        }
+    }
+
+    /// Starts compiling the module at the canonical `path`, returning its
+    /// file id.
+    pub(crate) fn begin(&mut self, name: String, path: PathBuf) -> usize {
+        self.modules.push(Module {
+            name,
+            names: vec![],
+            path,
+            failed: false,
+            statements: Rc::new([]),
+            environment: None,
+        });
+
+        let file = self.modules.len() + MAIN_FILE;
+        self.loading.push(file);
+        file
+    }
+
+    /// Finishes compiling a module, with its statements if it compiled.
+    pub(crate) fn finish(&mut self, file: usize, statements: Option<Vec<Stmt>>) {
+        self.loading.pop();
+
+        let module = self.get_mut(file);
+        match statements {
+            Some(statements) => {
+                module.names = statements.iter().filter_map(declared_name).collect();
+                module.statements = statements.into();
+            }
+            None => module.failed = true,
+        }
+    }
+
+    pub(crate) fn record_import(&mut self, import: Span, file: usize) {
+        self.imports.insert(import, file);
+    }
+
+    /// The file the import statement at `import` loads.
+    pub(crate) fn imported(&self, import: &Span) -> Option<usize> {
+        self.imports.get(import).copied()
+    }
+}
+
+fn declared_name(statement: &Stmt) -> Option<String> {
+    match &statement.kind {
+        StmtKind::Var(name, _) | StmtKind::Function(name, ..) | StmtKind::Class(name, _) => {
+            Some(name.lexeme.clone())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lox::{Lox, LoxError};
+
+    /// Writes `files` to a directory of their own, returning the path of
+    /// the first.
+    fn write(test: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("jlox-{}-{}", std::process::id(), test));
+        for (name, source) in files {
+            let path = directory.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+
+        directory.join(files[0].0)
+    }
+
+    fn errors(test: &str, files: &[(&str, &str)]) -> Vec<String> {
+        let script = write(test, files);
+        let mut lox = Lox::new();
+        lox.set_error_output(std::io::sink());
+        lox.set_script_path(&script);
+
+        let source = std::fs::read_to_string(&script).unwrap();
+        match lox.check(source) {
+            Err(LoxError::Compile(errors)) => errors.iter().map(ToString::to_string).collect(),
+            result => panic!("expected compile errors, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn imports_are_relative_to_the_importer() {
+        let script = write(
+            "relative",
+            &[
+                ("main.lox", "import { a } from \"lib/a.lox\";"),
+                ("lib/a.lox", "import { b } from \"b.lox\";\nvar a = b;"),
+                ("lib/b.lox", "var b = 1;"),
+            ],
+        );
+
+        let mut lox = Lox::new();
+        lox.set_script_path(&script);
+        let result = lox.check(std::fs::read_to_string(&script).unwrap());
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(
+            errors(
+                "unknown",
+                &[
+                    ("main.lox", "import { a, c } from \"lib.lox\";"),
+                    ("lib.lox", "var a; fun b() {}"),
+                ]
+            ),
+            vec!["[line 1] Error at 'c': Module 'lib.lox' has no 'c'."]
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        assert_eq!(
+            errors(
+                "cycle",
+                &[
+                    ("main.lox", "import \"a.lox\";"),
+                    ("a.lox", "import \"b.lox\";"),
+                    ("b.lox", "import \"a.lox\";"),
+                ]
+            ),
+            vec![
+                "[line 1] Error at '\"a.lox\"': In module 'a.lox': [line 1] Error at '\"b.lox\"': \
+                 In module 'b.lox': [line 1] Error at '\"a.lox\"': \
+                 Import cycle: a.lox -> b.lox -> a.lox."
+            ]
+        );
+        assert_eq!(
+            errors("self", &[("main.lox", "import \"main.lox\";")]),
+            vec!["[line 1] Error at '\"main.lox\"': Import cycle: main.lox -> main.lox."]
+        );
+    }
+
+    #[test]
+    fn errors_in_modules() {
+        assert_eq!(
+            errors(
+                "errors",
+                &[
+                    ("main.lox", "import \"lib.lox\";\n{ import \"lib.lox\"; }"),
+                    ("lib.lox", "print ;"),
+                ]
+            ),
+            vec![
+                "[line 1] Error at '\"lib.lox\"': In module 'lib.lox': \
+                 [line 1] Error at ';': Expect expression.",
+                "[line 2] Error at 'import': Can't import inside a block or function.",
+            ]
+        );
+
+        let missing = errors("missing", &[("main.lox", "import \"nope.lox\";")]);
+        assert!(missing[0]
+            .starts_with("[line 1] Error at '\"nope.lox\"': Can't read module 'nope.lox': "));
+    }
+}
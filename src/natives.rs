@@ -0,0 +1,775 @@
+//! Native functions defined in the global scope, the namespaces grouping
+//! related ones, and the methods of built-in types.
+//!
+//! The natives that touch files can be denied to a session, to sandbox
+//! scripts that aren't trusted.
+
+use std::fmt::Display;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::callable::{NativeFn, NativeFunction, NativeMethod, NativeMethodFn};
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_instance::LoxInstance;
+use crate::object::Object;
+use crate::token::Token;
+
+/// The name of every global the natives define, including `clock`, sorted.
+pub const NAMES: [&str; 18] = [
+    "appendFile",
+    "args",
+    "className",
+    "clock",
+    "exists",
+    "fields",
+    "hasField",
+    "isInstance",
+    "listDir",
+    "math",
+    "mkdir",
+    "num",
+    "readFile",
+    "readLines",
+    "remove",
+    "str",
+    "type",
+    "writeFile",
+];
+
+const FUNCTIONS: [(&str, usize, NativeFn); 8] = [
+    ("args", 0, args),
+    ("className", 1, class_name),
+    ("fields", 1, fields),
+    ("hasField", 2, has_field),
+    ("isInstance", 2, is_instance),
+    ("num", 1, num),
+    ("str", 1, str),
+    ("type", 1, type_of),
+];
+
+const FILE_FUNCTIONS: [(&str, usize, NativeFn); 8] = [
+    ("appendFile", 2, append_file),
+    ("exists", 1, exists),
+    ("listDir", 1, list_dir),
+    ("mkdir", 1, mkdir),
+    ("readFile", 1, read_file),
+    ("readLines", 1, read_lines),
+    ("remove", 1, remove),
+    ("writeFile", 2, write_file),
+];
+
+const MATH_CONSTANTS: [(&str, f64); 3] = [
+    ("inf", f64::INFINITY),
+    ("nan", f64::NAN),
+    ("pi", std::f64::consts::PI),
+];
+
+const MATH_FUNCTIONS: [(&str, usize, NativeFn); 17] = [
+    ("abs", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::abs)
+    }),
+    ("atan2", 2, |_, paren, arguments| {
+        binary(paren, &arguments, f64::atan2)
+    }),
+    ("ceil", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::ceil)
+    }),
+    ("cos", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::cos)
+    }),
+    ("div", 2, math_div),
+    ("exp", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::exp)
+    }),
+    ("floor", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::floor)
+    }),
+    ("isNaN", 1, math_is_nan),
+    ("log", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::ln)
+    }),
+    ("max", 2, |_, paren, arguments| {
+        binary(paren, &arguments, f64::max)
+    }),
+    ("min", 2, |_, paren, arguments| {
+        binary(paren, &arguments, f64::min)
+    }),
+    ("mod", 2, math_mod),
+    ("pow", 2, |_, paren, arguments| {
+        binary(paren, &arguments, f64::powf)
+    }),
+    ("round", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::round)
+    }),
+    ("sin", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::sin)
+    }),
+    ("sqrt", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::sqrt)
+    }),
+    ("tan", 1, |_, paren, arguments| {
+        unary(paren, &arguments, f64::tan)
+    }),
+];
+
+const LIST_METHODS: [(&str, usize, NativeMethodFn); 2] =
+    [("get", 1, list_get), ("len", 0, list_len)];
+
+const STRING_METHODS: [(&str, usize, NativeMethodFn); 11] = [
+    ("chars", 0, string_chars),
+    ("contains", 1, string_contains),
+    ("indexOf", 1, string_index_of),
+    ("len", 0, string_len),
+    ("lower", 0, string_lower),
+    ("replace", 2, string_replace),
+    ("split", 1, string_split),
+    ("startsWith", 1, string_starts_with),
+    ("substring", 2, string_substring),
+    ("trim", 0, string_trim),
+    ("upper", 0, string_upper),
+];
+
+/// A global grouping related natives, such as `math`, whose members are
+/// reached as its properties.
+#[derive(Debug)]
+pub struct Namespace {
+    name: &'static str,
+    members: Vec<(&'static str, Rc<Object>)>,
+}
+
+impl Namespace {
+    pub fn get(&self, name: &str) -> Option<Rc<Object>> {
+        self.members
+            .iter()
+            .find(|(member, _)| *member == name)
+            .map(|(_, value)| Rc::clone(value))
+    }
+
+    /// The names of its members, sorted.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.members.iter().map(|(name, _)| *name).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<namespace {}>", self.name)
+    }
+}
+
+/// Defines every native function and namespace in `globals`.
+pub fn define_functions(globals: &mut Environment) {
+    for (name, arity, function) in FUNCTIONS.into_iter().chain(FILE_FUNCTIONS) {
+        globals.define(name, function_object(name, arity, function));
+    }
+
+    let constants = MATH_CONSTANTS
+        .into_iter()
+        .map(|(name, value)| (name, Rc::new(Object::Number(value))));
+    let functions = MATH_FUNCTIONS
+        .into_iter()
+        .map(|(name, arity, function)| (name, function_object(name, arity, function)));
+    let math = Namespace {
+        name: "math",
+        members: constants.chain(functions).collect(),
+    };
+    globals.define("math", Rc::new(Object::Namespace(math)));
+}
+
+fn function_object(name: &'static str, arity: usize, function: NativeFn) -> Rc<Object> {
+    let function = NativeFunction::new(name, arity, function);
+    Rc::new(Object::Callable(Box::new(function)))
+}
+
+/// Looks up the method called `name` on a built-in value, bound to it.
+pub fn method(receiver: &Rc<Object>, name: &str) -> Option<Rc<Object>> {
+    let methods: &[(&'static str, usize, NativeMethodFn)] = match **receiver {
+        Object::List(_) => &LIST_METHODS,
+        Object::String(_) => &STRING_METHODS,
+        _ => &[],
+    };
+
+    methods
+        .iter()
+        .find(|(method, ..)| *method == name)
+        .map(|&(name, arity, function)| {
+            let method = NativeMethod::new(Rc::clone(receiver), name, arity, function);
+            Rc::new(Object::Callable(Box::new(method)))
+        })
+}
+
+/// The names of the methods of a built-in value, sorted.
+pub fn method_names(receiver: &Object) -> Vec<&'static str> {
+    match receiver {
+        Object::List(_) => LIST_METHODS.iter().map(|(name, ..)| *name).collect(),
+        Object::String(_) => STRING_METHODS.iter().map(|(name, ..)| *name).collect(),
+        Object::Namespace(namespace) => namespace.names(),
+        _ => vec![],
+    }
+}
+
+/// The arguments the script was run with, as a list of strings.
+fn args(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let args = interpreter
+        .args()
+        .iter()
+        .map(|arg| Rc::new(Object::String(arg.clone())))
+        .collect();
+
+    Ok(Rc::new(Object::List(args)))
+}
+
+/// Converts a value to the string it prints as.
+fn str(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    Ok(Rc::new(Object::String(arguments[0].to_string())))
+}
+
+/// Converts a string to a number, or to nil if it isn't one.
+fn num(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    match &*arguments[0] {
+        Object::Number(_) => Ok(Rc::clone(&arguments[0])),
+        Object::String(string) => match parse_number(string.trim()) {
+            Some(number) => Ok(Rc::new(Object::Number(number))),
+            None => Ok(Rc::new(Object::Nil)),
+        },
+        _ => Err(RuntimeError::new(
+            paren.clone(),
+            "Argument must be a string.",
+        )),
+    }
+}
+
+/// Parses `text` if it's a number as Lox writes them, digits with an
+/// optional fractional part, optionally signed. Rust's own syntax, such as
+/// `inf` or `1e3`, isn't accepted.
+fn parse_number(text: &str) -> Option<f64> {
+    let unsigned = text.strip_prefix(['-', '+']).unwrap_or(text);
+    let (whole, fraction) = match unsigned.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (unsigned, None),
+    };
+    let digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    if !digits(whole) || fraction.is_some_and(|fraction| !digits(fraction)) {
+        return None;
+    }
+
+    text.parse().ok()
+}
+
+/// The name of the type of a value, e.g. `"number"`.
+fn type_of(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let name = match *arguments[0] {
+        Object::Nil => "nil",
+        Object::Boolean(_) => "boolean",
+        Object::Number(_) => "number",
+        Object::String(_) => "string",
+        Object::Callable(_) => "function",
+        Object::Class(_) => "class",
+        Object::Instance(_) => "instance",
+        Object::List(_) => "list",
+        Object::Namespace(_) => "namespace",
+    };
+
+    Ok(Rc::new(Object::String(name.to_string())))
+}
+
+/// Whether a value is an instance of a class.
+fn is_instance(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let Object::Class(class) = &*arguments[1] else {
+        return Err(RuntimeError::new(
+            paren.clone(),
+            "Second argument must be a class.",
+        ));
+    };
+
+    let is_instance = match &*arguments[0] {
+        Object::Instance(instance) => instance.borrow().class().is(class),
+        _ => false,
+    };
+    Ok(Rc::new(Object::Boolean(is_instance)))
+}
+
+/// The name of an instance's class.
+fn class_name(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let name = instance(paren, &arguments[0], |instance| {
+        instance.class().name.clone()
+    })?;
+    Ok(Rc::new(Object::String(name)))
+}
+
+/// The names of an instance's fields, sorted.
+fn fields(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let fields = instance(paren, &arguments[0], LoxInstance::fields)?;
+    Ok(strings(fields.into_iter().map(|(name, _)| name)))
+}
+
+fn has_field(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let name = string_argument(paren, &arguments[1])?;
+    let has_field = instance(paren, &arguments[0], |instance| {
+        instance.field(name).is_some()
+    })?;
+    Ok(Rc::new(Object::Boolean(has_field)))
+}
+
+/// Applies `f` to the instance an introspection native is given.
+fn instance<T>(
+    paren: &Token,
+    argument: &Object,
+    f: impl FnOnce(&LoxInstance) -> T,
+) -> Result<T, RuntimeError> {
+    match argument {
+        Object::Instance(instance) => Ok(f(&instance.borrow())),
+        _ => Err(RuntimeError::new(
+            paren.clone(),
+            "Argument must be an instance.",
+        )),
+    }
+}
+
+/// The path a file native is given, if scripts may touch files.
+fn path<'a>(
+    interpreter: &Interpreter,
+    paren: &Token,
+    argument: &'a Object,
+) -> Result<&'a str, RuntimeError> {
+    if !interpreter.file_access() {
+        return Err(RuntimeError::new(paren.clone(), "File access is disabled."));
+    }
+
+    string_argument(paren, argument)
+}
+
+/// Turns the error from doing `action` to the file at `path` into a runtime
+/// error.
+fn io_error(paren: &Token, action: &str, path: &str, err: std::io::Error) -> RuntimeError {
+    RuntimeError::new(
+        paren.clone(),
+        format!("Can't {} '{}': {}.", action, path, err),
+    )
+}
+
+fn read_file(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    let contents =
+        std::fs::read_to_string(path).map_err(|err| io_error(paren, "read", path, err))?;
+    Ok(Rc::new(Object::String(contents)))
+}
+
+/// The lines of a file, without their line endings.
+fn read_lines(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    let contents =
+        std::fs::read_to_string(path).map_err(|err| io_error(paren, "read", path, err))?;
+    Ok(strings(contents.lines().map(String::from)))
+}
+
+/// Writes a string to a file, replacing what was in it.
+fn write_file(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    let contents = string_argument(paren, &arguments[1])?;
+    std::fs::write(path, contents).map_err(|err| io_error(paren, "write", path, err))?;
+    Ok(Rc::new(Object::Nil))
+}
+
+/// Writes a string to the end of a file, creating it if need be.
+fn append_file(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    let contents = string_argument(paren, &arguments[1])?;
+    std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| io_error(paren, "write", path, err))?;
+    Ok(Rc::new(Object::Nil))
+}
+
+fn exists(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    Ok(Rc::new(Object::Boolean(Path::new(path).exists())))
+}
+
+/// The names of the entries in a directory, sorted.
+fn list_dir(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    let mut names = std::fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<String>, std::io::Error>>()
+        })
+        .map_err(|err| io_error(paren, "list", path, err))?;
+    names.sort();
+    Ok(strings(names.into_iter()))
+}
+
+/// Creates a directory, along with any parents it's missing.
+fn mkdir(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    std::fs::create_dir_all(path).map_err(|err| io_error(paren, "create", path, err))?;
+    Ok(Rc::new(Object::Nil))
+}
+
+/// Removes a file, or a directory if it's empty.
+fn remove(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let path = path(interpreter, paren, &arguments[0])?;
+    let result = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir(path),
+        _ => std::fs::remove_file(path),
+    };
+    result.map_err(|err| io_error(paren, "remove", path, err))?;
+    Ok(Rc::new(Object::Nil))
+}
+
+/// The arguments of a math function, which must all be numbers.
+fn numbers(paren: &Token, arguments: &[Rc<Object>]) -> Result<Vec<f64>, RuntimeError> {
+    arguments
+        .iter()
+        .map(|argument| match **argument {
+            Object::Number(number) => Ok(number),
+            _ if arguments.len() == 1 => Err(RuntimeError::new(
+                paren.clone(),
+                "Argument must be a number.",
+            )),
+            _ => Err(RuntimeError::new(
+                paren.clone(),
+                "Arguments must be numbers.",
+            )),
+        })
+        .collect()
+}
+
+fn unary(
+    paren: &Token,
+    arguments: &[Rc<Object>],
+    function: fn(f64) -> f64,
+) -> Result<Rc<Object>, RuntimeError> {
+    let numbers = numbers(paren, arguments)?;
+    Ok(Rc::new(Object::Number(function(numbers[0]))))
+}
+
+fn binary(
+    paren: &Token,
+    arguments: &[Rc<Object>],
+    function: fn(f64, f64) -> f64,
+) -> Result<Rc<Object>, RuntimeError> {
+    let numbers = numbers(paren, arguments)?;
+    Ok(Rc::new(Object::Number(function(numbers[0], numbers[1]))))
+}
+
+/// The divisor of `div` or `mod`, which can't be zero.
+fn divisor(paren: &Token, divisor: f64) -> Result<f64, RuntimeError> {
+    if divisor == 0.0 {
+        return Err(RuntimeError::new(paren.clone(), "Division by zero."));
+    }
+
+    Ok(divisor)
+}
+
+/// Divides, rounding the quotient down.
+fn math_div(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let numbers = numbers(paren, &arguments)?;
+    let quotient = numbers[0] / divisor(paren, numbers[1])?;
+    Ok(Rc::new(Object::Number(quotient.floor())))
+}
+
+/// The remainder of `div`, which has the sign of the divisor.
+fn math_mod(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let numbers = numbers(paren, &arguments)?;
+    let (dividend, divisor) = (numbers[0], divisor(paren, numbers[1])?);
+    let remainder = dividend - divisor * (dividend / divisor).floor();
+    Ok(Rc::new(Object::Number(remainder)))
+}
+
+fn math_is_nan(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let numbers = numbers(paren, &arguments)?;
+    Ok(Rc::new(Object::Boolean(numbers[0].is_nan())))
+}
+
+fn list_get(
+    list: &Object,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let Object::List(elements) = list else {
+        return Err(RuntimeError::new(paren.clone(), "Expected a list."));
+    };
+
+    elements
+        .get(index(paren, &arguments[0])?)
+        .cloned()
+        .ok_or_else(|| RuntimeError::new(paren.clone(), "Index out of range."))
+}
+
+fn list_len(
+    list: &Object,
+    paren: &Token,
+    _arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    match list {
+        Object::List(elements) => Ok(Rc::new(Object::Number(elements.len() as f64))),
+        _ => Err(RuntimeError::new(paren.clone(), "Expected a list.")),
+    }
+}
+
+/// An index into a list or string, which must be a non-negative integer.
+fn index(paren: &Token, argument: &Object) -> Result<usize, RuntimeError> {
+    match *argument {
+        Object::Number(index) if index.fract() == 0.0 && index >= 0.0 => Ok(index as usize),
+        _ => Err(RuntimeError::new(
+            paren.clone(),
+            "Index must be a non-negative integer.",
+        )),
+    }
+}
+
+/// The receiver of a string method.
+fn string<'a>(receiver: &'a Object, paren: &Token) -> Result<&'a str, RuntimeError> {
+    match receiver {
+        Object::String(string) => Ok(string),
+        _ => Err(RuntimeError::new(paren.clone(), "Expected a string.")),
+    }
+}
+
+fn string_argument<'a>(paren: &Token, argument: &'a Object) -> Result<&'a str, RuntimeError> {
+    match argument {
+        Object::String(string) => Ok(string),
+        _ => Err(RuntimeError::new(
+            paren.clone(),
+            "Argument must be a string.",
+        )),
+    }
+}
+
+fn strings(strings: impl Iterator<Item = String>) -> Rc<Object> {
+    Rc::new(Object::List(
+        strings
+            .map(|string| Rc::new(Object::String(string)))
+            .collect(),
+    ))
+}
+
+/// Its characters, as a list of strings.
+fn string_chars(
+    receiver: &Object,
+    paren: &Token,
+    _arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    Ok(strings(string.chars().map(String::from)))
+}
+
+fn string_contains(
+    receiver: &Object,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    let part = string_argument(paren, &arguments[0])?;
+    Ok(Rc::new(Object::Boolean(string.contains(part))))
+}
+
+/// The index of the character where `part` first starts, or -1 if it doesn't
+/// occur.
+fn string_index_of(
+    receiver: &Object,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    let part = string_argument(paren, &arguments[0])?;
+    let index = match string.find(part) {
+        Some(byte) => string[..byte].chars().count() as f64,
+        None => -1.0,
+    };
+    Ok(Rc::new(Object::Number(index)))
+}
+
+/// The number of characters in it.
+fn string_len(
+    receiver: &Object,
+    paren: &Token,
+    _arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    Ok(Rc::new(Object::Number(string.chars().count() as f64)))
+}
+
+fn string_lower(
+    receiver: &Object,
+    paren: &Token,
+    _arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    Ok(Rc::new(Object::String(string.to_lowercase())))
+}
+
+/// Replaces every occurrence of a string with another.
+fn string_replace(
+    receiver: &Object,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    let from = string_argument(paren, &arguments[0])?;
+    let to = string_argument(paren, &arguments[1])?;
+    Ok(Rc::new(Object::String(string.replace(from, to))))
+}
+
+fn string_split(
+    receiver: &Object,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    let separator = string_argument(paren, &arguments[0])?;
+    if separator.is_empty() {
+        return Err(RuntimeError::new(
+            paren.clone(),
+            "Separator must not be empty.",
+        ));
+    }
+
+    Ok(strings(string.split(separator).map(String::from)))
+}
+
+fn string_starts_with(
+    receiver: &Object,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    let prefix = string_argument(paren, &arguments[0])?;
+    Ok(Rc::new(Object::Boolean(string.starts_with(prefix))))
+}
+
+/// The characters from index `start` up to, but not including, `end`.
+fn string_substring(
+    receiver: &Object,
+    paren: &Token,
+    arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    let start = index(paren, &arguments[0])?;
+    let end = index(paren, &arguments[1])?;
+    if start > end || end > string.chars().count() {
+        return Err(RuntimeError::new(paren.clone(), "Index out of range."));
+    }
+
+    let substring = string.chars().skip(start).take(end - start).collect();
+    Ok(Rc::new(Object::String(substring)))
+}
+
+fn string_trim(
+    receiver: &Object,
+    paren: &Token,
+    _arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    Ok(Rc::new(Object::String(string.trim().to_string())))
+}
+
+fn string_upper(
+    receiver: &Object,
+    paren: &Token,
+    _arguments: Vec<Rc<Object>>,
+) -> Result<Rc<Object>, RuntimeError> {
+    let string = string(receiver, paren)?;
+    Ok(Rc::new(Object::String(string.to_uppercase())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_the_globals_defined() {
+        let mut globals: Vec<String> = Interpreter::new()
+            .globals()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        globals.sort_unstable();
+        assert_eq!(globals, NAMES);
+    }
+}
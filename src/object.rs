@@ -7,6 +7,7 @@ use std::rc::Rc;
 use crate::callable::Callable;
 use crate::lox_class::LoxClass;
 use crate::lox_instance::LoxInstance;
+use crate::natives::Namespace;
 use crate::token::Literal;
 
 #[derive(Debug)]
@@ -18,6 +19,8 @@ pub enum Object {
     Callable(Box<dyn Callable>),
     Class(LoxClass), // TODO: collapse into Callable?
     Instance(RefCell<LoxInstance>),
+    List(Vec<Rc<Object>>),
+    Namespace(Namespace),
 }
 
 impl From<&Literal> for Object {
@@ -171,6 +174,11 @@ impl Display for Object {
             Object::Callable(fun) => Debug::fmt(fun, f),
             Object::Class(class) => write!(f, "{}", class),
             Object::Instance(instance) => write!(f, "{}", instance.borrow()),
+            Object::List(elements) => {
+                let elements: Vec<String> = elements.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Object::Namespace(namespace) => write!(f, "{}", namespace),
         }
     }
 }
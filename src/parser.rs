@@ -1,8 +1,16 @@
-use crate::token::{self, Token, TokenType};
+use crate::error::CompileError;
+use crate::token::{self, Span, Token, TokenType, MAIN_FILE};
 use std::fmt::Display;
 
+/// An expression, along with where it is in the source.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExprKind {
     Logical(
         Box<Expr>, // left
         Token,     // operator
@@ -41,70 +49,93 @@ pub enum Expr {
     This(Token), // keyword
 }
 
+/// Prints expressions as Lisp-style S-expressions, e.g. `(+ 1 (* 2 3))`.
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Expr::Logical(left, operator, right) | Expr::Binary(left, operator, right) => {
+        match &self.kind {
+            ExprKind::Logical(left, operator, right) | ExprKind::Binary(left, operator, right) => {
                 write!(f, "({} {} {})", operator.lexeme, left, right)
             }
-            Expr::Grouping(expr) => write!(f, "(group {})", expr),
-            Expr::Literal(literal) => write!(f, "{}", literal),
-            Expr::Unary(operator, right) => write!(f, "({} {})", operator.lexeme, right),
-            Expr::Variable(name) => write!(f, "{}", name),
-            Expr::Assign(name, expr) => write!(f, "{} = {}", name, expr),
-            Expr::Call(callee, _paren, args) => write!(f, "{}({:?})", callee, args),
-            Expr::Get(object, name) => write!(f, "{}.{}", object, name),
-            Expr::Set(object, name, value) => write!(f, "{}.{} = {}", object, name, value),
-            Expr::This(_keyword) => write!(f, "this"),
+            ExprKind::Grouping(expr) => write!(f, "(group {})", expr),
+            ExprKind::Literal(token::Literal::String(string)) => write!(f, "{:?}", string),
+            ExprKind::Literal(literal) => write!(f, "{}", literal),
+            ExprKind::Unary(operator, right) => write!(f, "({} {})", operator.lexeme, right),
+            ExprKind::Variable(name) => write!(f, "{}", name.lexeme),
+            ExprKind::Assign(name, expr) => write!(f, "(= {} {})", name.lexeme, expr),
+            ExprKind::Call(callee, _paren, args) => {
+                write!(f, "(call {}", callee)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Get(object, name) => write!(f, "(get {} {})", object, name.lexeme),
+            ExprKind::Set(object, name, value) => {
+                write!(f, "(set {} {} {})", object, name.lexeme, value)
+            }
+            ExprKind::This(_keyword) => write!(f, "this"),
         }
     }
 }
 
 impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    // Compound expressions span from their first child or token to their last
+
     fn logical(left: Self, operator: Token, right: Self) -> Self {
-        Self::Logical(Box::new(left), operator, Box::new(right))
+        let span = left.span.to(right.span);
+        Self::new(
+            ExprKind::Logical(Box::new(left), operator, Box::new(right)),
+            span,
+        )
     }
 
     fn binary(left: Self, operator: Token, right: Self) -> Self {
-        Self::Binary(Box::new(left), operator, Box::new(right))
+        let span = left.span.to(right.span);
+        Self::new(
+            ExprKind::Binary(Box::new(left), operator, Box::new(right)),
+            span,
+        )
     }
 
-    fn grouping(expr: Self) -> Self {
-        Self::Grouping(Box::new(expr))
+    fn unary(operator: Token, right: Self) -> Self {
+        let span = operator.span(right.span.file).to(right.span);
+        Self::new(ExprKind::Unary(operator, Box::new(right)), span)
     }
 
-    fn unary(operator: Token, right: Self) -> Self {
-        Self::Unary(operator, Box::new(right))
+    fn assign(name: Token, value: Self) -> Self {
+        let span = name.span(value.span.file).to(value.span);
+        Self::new(ExprKind::Assign(name, Box::new(value)), span)
     }
 
-    fn assign(name: Token, expr: Self) -> Self {
-        Self::Assign(name, Box::new(expr))
+    fn call(callee: Self, paren: Token, arguments: Vec<Self>) -> Self {
+        let span = callee.span.to(paren.span(callee.span.file));
+        Self::new(ExprKind::Call(Box::new(callee), paren, arguments), span)
     }
 
-    fn call(callee: Expr, paren: Token, arguments: Vec<Expr>) -> Self {
-        Self::Call(Box::new(callee), paren, arguments)
+    fn get(object: Self, name: Token) -> Self {
+        let span = object.span.to(name.span(object.span.file));
+        Self::new(ExprKind::Get(Box::new(object), name), span)
     }
 
     fn set(object: Box<Self>, name: Token, value: Self) -> Self {
-        Self::Set(object, name, Box::new(value))
+        let span = object.span.to(value.span);
+        Self::new(ExprKind::Set(object, name, Box::new(value)), span)
     }
 }
 
-fn _test_pretty_print() {
-    let expr = Expr::binary(
-        Expr::unary(
-            Token::new(TokenType::Minus, "-".into(), token::Literal::None, 1, 0),
-            Expr::Literal(token::Literal::Number(123.0.into())),
-        ),
-        Token::new(TokenType::Star, "*".into(), token::Literal::None, 1, 0),
-        Expr::grouping(Expr::Literal(token::Literal::Number(45.67.into()))),
-    );
-
-    println!("{}", expr);
+/// A statement, along with where it is in the source.
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
-pub enum Stmt {
+pub enum StmtKind {
     Block(Vec<Stmt>),
     Class(
         Token,     // name
@@ -121,47 +152,186 @@ pub enum Stmt {
         Vec<Token>, // parameters
         Vec<Stmt>,  // body
     ),
-    Print(Expr),
+    Import(
+        Token,              // keyword
+        Token,              // path
+        Option<Vec<Token>>, // names, or `None` to import everything
+    ),
+    Print(
+        Token, // keyword
+        Expr,  // value
+    ),
     Return(
         Token,        // keyword
         Option<Expr>, // return valuue
     ),
     Var(
-        String,       // name TODO: Make this a token
+        Token,        // name
         Option<Expr>, // initializer
     ),
     While(
+        Token,     // keyword
         Expr,      // condition
         Box<Stmt>, // body
     ),
 }
 
+/// Prints statements as S-expressions, with the statements inside blocks,
+/// functions, classes and control flow on their own indented lines.
+impl Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, indent: usize) -> std::fmt::Result {
+        let nested = |f: &mut std::fmt::Formatter<'_>, stmt: &Stmt| {
+            write!(f, "\n{:width$}", "", width = (indent + 1) * 2)?;
+            stmt.fmt_indented(f, indent + 1)
+        };
+
+        match &self.kind {
+            StmtKind::Block(statements) => {
+                write!(f, "(block")?;
+                for statement in statements {
+                    nested(f, statement)?;
+                }
+            }
+            StmtKind::Class(name, methods) => {
+                write!(f, "(class {}", name.lexeme)?;
+                for method in methods {
+                    nested(f, method)?;
+                }
+            }
+            StmtKind::If(condition, then_branch, else_branch) => {
+                write!(f, "(if {}", condition)?;
+                nested(f, then_branch)?;
+                if let Some(else_branch) = else_branch.as_ref() {
+                    nested(f, else_branch)?;
+                }
+            }
+            StmtKind::Expression(expr) => write!(f, "(expr {}", expr)?,
+            StmtKind::Function(name, params, body) => {
+                let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+                write!(f, "(fun {} ({})", name.lexeme, params.join(" "))?;
+                for statement in body {
+                    nested(f, statement)?;
+                }
+            }
+            StmtKind::Import(_keyword, path, None) => write!(f, "(import {}", path.lexeme)?,
+            StmtKind::Import(_keyword, path, Some(names)) => {
+                let names: Vec<&str> = names.iter().map(|name| name.lexeme.as_str()).collect();
+                write!(f, "(import ({}) {}", names.join(" "), path.lexeme)?
+            }
+            StmtKind::Print(_keyword, expr) => write!(f, "(print {}", expr)?,
+            StmtKind::Return(_keyword, None) => write!(f, "(return")?,
+            StmtKind::Return(_keyword, Some(value)) => write!(f, "(return {}", value)?,
+            StmtKind::Var(name, None) => write!(f, "(var {}", name.lexeme)?,
+            StmtKind::Var(name, Some(initializer)) => {
+                write!(f, "(var {} {}", name.lexeme, initializer)?
+            }
+            StmtKind::While(_keyword, condition, body) => {
+                write!(f, "(while {}", condition)?;
+                nested(f, body)?;
+            }
+        }
+
+        write!(f, ")")
+    }
+}
+
+/// How deeply statements and expressions may nest. Every later pass walks
+/// the tree recursively, so this keeps them from overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    depth: usize,
+    errors: Vec<CompileError>,
+    file: usize, // the tokens came from, for spans
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        if tokens.last().map(|token| token.token_type) != Some(TokenType::Eof) {
+            let line = tokens.last().map_or(1, |token| token.line);
+            tokens.push(Token::new(
+                TokenType::Eof,
+                String::new(),
+                token::Literal::None,
+                line,
+                0,
+            ));
+        }
+
+        Self {
+            tokens,
+            current: 0,
+            depth: 0,
+            errors: vec![],
+            file: MAIN_FILE,
+        }
+    }
+
+    /// Marks the spans of everything parsed as being in `file`.
+    pub fn in_file(mut self, file: usize) -> Self {
+        self.file = file;
+        self
     }
 
-    pub fn parse(&mut self) -> Option<Vec<Stmt>> {
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<CompileError>> {
         let mut statements = vec![];
 
         while !self.is_at_end() {
-            statements.push(self.declaration().ok()?);
+            // Errors are recorded as they're found and the parser
+            // synchronizes, so keep going to report as many as possible
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(ParseError) => self.depth = 0,
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Parses the tokens as a single expression with nothing after it.
+    pub fn parse_expression(&mut self) -> Result<Expr, Vec<CompileError>> {
+        let expr = self.expression();
+
+        if expr.is_ok() && !self.is_at_end() {
+            self.error(&self.peek(), "Expect end of expression.");
         }
 
-        Some(statements)
+        match expr {
+            Ok(expr) if self.errors.is_empty() => Ok(expr),
+            _ => Err(std::mem::take(&mut self.errors)),
+        }
     }
 
     fn expression(&mut self) -> Result<Expr, ParseError> {
-        self.assignment()
+        let depth = self.depth;
+        self.nest()?;
+        let expr = self.assignment()?;
+        self.depth = depth;
+
+        Ok(expr)
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
-        if self.match_(&[TokenType::For]) {
+        let depth = self.depth;
+        self.nest()?;
+
+        let statement = if self.match_(&[TokenType::For]) {
             self.for_statement()
         } else if self.match_(&[TokenType::If]) {
             self.if_statement()
@@ -172,13 +342,22 @@ impl Parser {
         } else if self.match_(&[TokenType::While]) {
             self.while_statement()
         } else if self.match_(&[TokenType::LeftBrace]) {
-            Ok(Stmt::Block(self.block()?))
+            let brace = self.previous();
+            let statements = self.block()?;
+            Ok(Stmt::new(
+                StmtKind::Block(statements),
+                self.span_from(&brace),
+            ))
         } else {
             self.expression_statement()
-        }
+        }?;
+        self.depth = depth;
+
+        Ok(statement)
     }
 
     fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
 
         let initializer = if self.match_(&[TokenType::Semicolon]) {
@@ -190,13 +369,15 @@ impl Parser {
         };
 
         let condition = if self.check(TokenType::Semicolon) {
-            None
+            // No condition loops forever, as though it were `true`
+            let span = self.span(&self.peek());
+            Expr::new(ExprKind::Literal(token::Literal::True), span)
         } else {
-            Some(self.expression()?)
+            self.expression()?
         };
         self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
 
-        let increment = if self.check(TokenType::Semicolon) {
+        let increment = if self.check(TokenType::RightParen) {
             None
         } else {
             Some(self.expression()?)
@@ -204,16 +385,21 @@ impl Parser {
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
         let body = self.statement()?;
+        // The statements the loop desugars to all span the whole loop
+        let span = self.span_from(&keyword);
         let body = match increment {
-            Some(expr) => Stmt::Block(vec![body, Stmt::Expression(expr)]),
+            Some(expr) => {
+                let expr_span = expr.span;
+                let increment = Stmt::new(StmtKind::Expression(expr), expr_span);
+                Stmt::new(StmtKind::Block(vec![body, increment]), span)
+            }
             None => body,
         };
 
-        let condition = condition.unwrap_or(Expr::Literal(token::Literal::True));
-        let body = Stmt::While(condition, Box::new(body));
+        let body = Stmt::new(StmtKind::While(keyword, condition, Box::new(body)), span);
 
         let body = match initializer {
-            Some(stmt) => Stmt::Block(vec![stmt, body]),
+            Some(stmt) => Stmt::new(StmtKind::Block(vec![stmt, body]), span),
             None => body,
         };
 
@@ -221,6 +407,7 @@ impl Parser {
     }
 
     fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
         self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
@@ -232,10 +419,9 @@ impl Parser {
             None
         };
 
-        Ok(Stmt::If(
-            condition,
-            Box::new(then_branch),
-            Box::new(else_branch),
+        Ok(Stmt::new(
+            StmtKind::If(condition, Box::new(then_branch), Box::new(else_branch)),
+            self.span_from(&keyword),
         ))
     }
 
@@ -257,16 +443,16 @@ impl Parser {
             self.function(FunctionKind::Function)
         } else if self.match_(&[TokenType::Var]) {
             self.var_declaration()
+        } else if self.match_(&[TokenType::Import]) {
+            self.import_declaration()
         } else {
             self.statement()
         }
-        .map_err(|err| {
-            self._synchronize();
-            err
-        })
+        .inspect_err(|_| self._synchronize())
     }
 
     fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
         let name = self.consume(TokenType::Identifier, "Expect class name.")?;
         self.consume(TokenType::LeftBrace, "Expect '{' after class name.")?;
 
@@ -278,10 +464,21 @@ impl Parser {
 
         self.consume(TokenType::RightBrace, "Expect '}' after class body.")?; // TODO
 
-        Ok(Stmt::Class(name, methods))
+        Ok(Stmt::new(
+            StmtKind::Class(name, methods),
+            self.span_from(&keyword),
+        ))
     }
 
     fn function(&mut self, kind: FunctionKind) -> Result<Stmt, ParseError> {
+        let depth = self.depth;
+        self.nest()?;
+
+        // Methods start at their name, functions at `fun`
+        let start = match kind {
+            FunctionKind::Function => self.previous(),
+            _ => self.peek(),
+        };
         let name = self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?;
         self.consume(
             TokenType::LeftParen,
@@ -292,7 +489,7 @@ impl Parser {
         if !self.check(TokenType::RightParen) {
             loop {
                 if parameters.len() >= 255 {
-                    print_error(self.peek(), "Can't have more than 255 parameters.");
+                    self.error(&self.peek(), "Can't have more than 255 parameters.");
                 }
 
                 parameters.push(self.consume(TokenType::Identifier, "Expect parameter name.")?);
@@ -310,27 +507,63 @@ impl Parser {
             &format!("Expect '{{' before {} body.", kind),
         )?;
         let body = self.block()?;
+        self.depth = depth;
 
-        Ok(Stmt::Function(name, parameters, body))
+        Ok(Stmt::new(
+            StmtKind::Function(name, parameters, body),
+            self.span_from(&start),
+        ))
     }
 
     fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
         let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
 
-        if self.match_(&[TokenType::Equal]) {
-            let initializer = Some(self.expression()?);
-            self.consume(
-                TokenType::Semicolon,
-                "Expect ';' after variable declaration.",
-            )?;
-            Ok(Stmt::Var(name.lexeme, initializer))
+        let initializer = if self.match_(&[TokenType::Equal]) {
+            Some(self.expression()?)
         } else {
-            self.consume(
-                TokenType::Semicolon,
-                "Expect ';' after variable declaration.",
-            )?;
-            Ok(Stmt::Var(name.lexeme, None))
-        }
+            None
+        };
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        )?;
+
+        Ok(Stmt::new(
+            StmtKind::Var(name, initializer),
+            self.span_from(&keyword),
+        ))
+    }
+
+    fn import_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+
+        let names = if self.match_(&[TokenType::LeftBrace]) {
+            let mut names = vec![];
+            loop {
+                names.push(self.consume(TokenType::Identifier, "Expect name to import.")?);
+                if !self.match_(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after imported names.")?;
+
+            // `from` is only a keyword here, so it can still name variables
+            if !(self.check(TokenType::Identifier) && self.peek().lexeme == "from") {
+                return Err(self.error(&self.peek(), "Expect 'from' after imported names."));
+            }
+            self.advance();
+
+            Some(names)
+        } else {
+            None
+        };
+
+        let path = self.consume(TokenType::String, "Expect module path.")?;
+        self.consume(TokenType::Semicolon, "Expect ';' after import.")?;
+
+        let span = self.span_from(&keyword);
+        Ok(Stmt::new(StmtKind::Import(keyword, path, names), span))
     }
 
     fn assignment(&mut self) -> Result<Expr, ParseError> {
@@ -338,12 +571,14 @@ impl Parser {
 
         if self.match_(&[TokenType::Equal]) {
             let equals = self.previous();
-            let value = self.assignment()?;
+            let value = self.expression()?;
 
-            match expr {
-                Expr::Variable(name) => return Ok(Expr::assign(name, value)),
-                Expr::Get(object, name) => return Ok(Expr::set(object, name, value)),
-                _ => print_error(equals, &format!("Invalid assignment target: {}", expr)),
+            match expr.kind {
+                ExprKind::Variable(name) => return Ok(Expr::assign(name, value)),
+                ExprKind::Get(object, name) => return Ok(Expr::set(object, name, value)),
+                _ => {
+                    self.error(&equals, "Invalid assignment target.");
+                }
             }
         }
 
@@ -353,12 +588,15 @@ impl Parser {
     fn or(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.and()?;
 
+        let depth = self.depth;
         while self.match_(&[TokenType::Or]) {
+            self.nest()?;
             let operator = self.previous();
             let right = self.and()?;
 
             expr = Expr::logical(expr, operator, right);
         }
+        self.depth = depth;
 
         Ok(expr)
     }
@@ -366,12 +604,15 @@ impl Parser {
     fn and(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.equality()?;
 
+        let depth = self.depth;
         while self.match_(&[TokenType::And]) {
+            self.nest()?;
             let operator = self.previous();
             let right = self.equality()?;
 
             expr = Expr::logical(expr, operator, right);
         }
+        self.depth = depth;
 
         Ok(expr)
     }
@@ -379,12 +620,15 @@ impl Parser {
     fn equality(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.comparison()?;
 
+        let depth = self.depth;
         while self.match_(&[TokenType::BangEqual, TokenType::EqualEqual]) {
+            self.nest()?;
             let operator = self.previous();
             let right: Expr = self.comparison()?;
 
             expr = Expr::binary(expr, operator.clone(), right);
         }
+        self.depth = depth;
 
         Ok(expr)
     }
@@ -401,9 +645,11 @@ impl Parser {
     }
 
     fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
         let expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
-        Ok(Stmt::Print(expr))
+        let span = self.span_from(&keyword);
+        Ok(Stmt::new(StmtKind::Print(keyword, expr), span))
     }
 
     fn return_statement(&mut self) -> Result<Stmt, ParseError> {
@@ -416,22 +662,29 @@ impl Parser {
         };
 
         self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
-        Ok(Stmt::Return(keyword, value))
+        let span = self.span_from(&keyword);
+        Ok(Stmt::new(StmtKind::Return(keyword, value), span))
     }
 
     fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
         let body = self.statement()?;
 
-        Ok(Stmt::While(condition, Box::new(body)))
+        let span = self.span_from(&keyword);
+        Ok(Stmt::new(
+            StmtKind::While(keyword, condition, Box::new(body)),
+            span,
+        ))
     }
 
     fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
         let expr = self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
-        Ok(Stmt::Expression(expr))
+        let semicolon = self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        let span = expr.span.to(self.span(&semicolon));
+        Ok(Stmt::new(StmtKind::Expression(expr), span))
     }
 
     fn check(&mut self, token_type: TokenType) -> bool {
@@ -458,27 +711,53 @@ impl Parser {
 
     // Can return Option<&Token> instead
     fn peek(&self) -> Token {
-        self.tokens.get(self.current).unwrap().clone()
+        // `new` guarantees there's a trailing EOF token to fall back on
+        let index = self.current.min(self.tokens.len() - 1);
+        self.tokens[index].clone()
     }
 
     fn previous(&self) -> Token {
-        self.tokens.get(self.current - 1).unwrap().clone()
+        self.tokens[self.current.saturating_sub(1)].clone()
+    }
+
+    fn span(&self, token: &Token) -> Span {
+        token.span(self.file)
+    }
+
+    /// The span from `start` to the token just consumed.
+    fn span_from(&self, start: &Token) -> Span {
+        self.span(start).to(self.span(&self.previous()))
+    }
+
+    /// Enters one more level of nesting, failing if the tree would get too
+    /// deep. Callers restore `depth` once they're done with the nested part.
+    fn nest(&mut self) -> Result<(), ParseError> {
+        self.depth += 1;
+
+        if self.depth > MAX_DEPTH {
+            Err(self.error(&self.peek(), "Too much nesting."))
+        } else {
+            Ok(())
+        }
     }
 
     fn comparison(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.term()?;
 
+        let depth = self.depth;
         while self.match_(&[
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
         ]) {
+            self.nest()?;
             let operator = self.previous();
             let right = self.term()?;
 
             expr = Expr::binary(expr, operator, right);
         }
+        self.depth = depth;
 
         Ok(expr)
     }
@@ -486,12 +765,15 @@ impl Parser {
     fn term(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.factor()?;
 
+        let depth = self.depth;
         while self.match_(&[TokenType::Minus, TokenType::Plus]) {
+            self.nest()?;
             let operator = self.previous();
             let right = self.factor()?;
 
             expr = Expr::binary(expr, operator, right);
         }
+        self.depth = depth;
 
         Ok(expr)
     }
@@ -499,12 +781,15 @@ impl Parser {
     fn factor(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.unary()?;
 
+        let depth = self.depth;
         while self.match_(&[TokenType::Star, TokenType::Slash]) {
+            self.nest()?;
             let operator = self.previous();
             let right = self.unary()?;
 
             expr = Expr::binary(expr, operator, right);
         }
+        self.depth = depth;
 
         Ok(expr)
     }
@@ -512,7 +797,11 @@ impl Parser {
     fn unary(&mut self) -> Result<Expr, ParseError> {
         if self.match_(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
+
+            let depth = self.depth;
+            self.nest()?;
             let right = self.unary()?;
+            self.depth = depth;
 
             Ok(Expr::unary(operator, right))
         } else {
@@ -523,17 +812,23 @@ impl Parser {
     fn call(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.primary()?;
 
+        let depth = self.depth;
         loop {
+            if self.check(TokenType::LeftParen) || self.check(TokenType::Dot) {
+                self.nest()?;
+            }
+
             if self.match_(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
             } else if self.match_(&[TokenType::Dot]) {
                 let name =
                     self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
-                expr = Expr::Get(Box::new(expr), name);
+                expr = Expr::get(expr, name);
             } else {
                 break;
             }
         }
+        self.depth = depth;
 
         Ok(expr)
     }
@@ -544,7 +839,7 @@ impl Parser {
         if !self.check(TokenType::RightParen) {
             loop {
                 if arguments.len() >= 255 {
-                    print_error(self.peek(), "Can't have more than 255 arguments.");
+                    self.error(&self.peek(), "Can't have more than 255 arguments.");
                 }
 
                 arguments.push(self.expression()?);
@@ -561,38 +856,49 @@ impl Parser {
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
-        if self.match_(&[TokenType::False]) {
-            Ok(Expr::Literal(token::Literal::False))
+        let kind = if self.match_(&[TokenType::False]) {
+            ExprKind::Literal(token::Literal::False)
         } else if self.match_(&[TokenType::True]) {
-            Ok(Expr::Literal(token::Literal::True))
+            ExprKind::Literal(token::Literal::True)
         } else if self.match_(&[TokenType::Nil]) {
-            Ok(Expr::Literal(token::Literal::Nil))
+            ExprKind::Literal(token::Literal::Nil)
         } else if self.match_(&[TokenType::Number, TokenType::String]) {
-            Ok(Expr::Literal(self.previous().literal))
+            ExprKind::Literal(self.previous().literal)
         } else if self.match_(&[TokenType::Identifier]) {
-            Ok(Expr::Variable(self.previous()))
+            ExprKind::Variable(self.previous())
         } else if self.match_(&[TokenType::This]) {
-            Ok(Expr::This(self.previous()))
+            ExprKind::This(self.previous())
         } else if self.match_(&[TokenType::LeftParen]) {
+            let paren = self.previous();
             let expr = self.expression()?;
             self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
 
-            Ok(Expr::grouping(expr))
+            return Ok(Expr::new(
+                ExprKind::Grouping(Box::new(expr)),
+                self.span_from(&paren),
+            ));
         } else {
-            print_error(self.peek(), "Expect expression.");
-            Err(ParseError)
-        }
+            return Err(self.error(&self.peek(), "Expect expression."));
+        };
+
+        Ok(Expr::new(kind, self.span(&self.previous())))
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token, ParseError> {
         if self.check(token_type) {
             Ok(self.advance())
         } else {
-            print_error(self.peek(), message);
-            Err(ParseError)
+            Err(self.error(&self.peek(), message))
         }
     }
 
+    /// Records an error. The returned `ParseError` can be used to unwind
+    /// to the nearest statement boundary.
+    fn error(&mut self, token: &Token, message: &str) -> ParseError {
+        self.errors.push(CompileError::at_token(token, message));
+        ParseError
+    }
+
     fn _synchronize(&mut self) {
         self.advance();
 
@@ -607,6 +913,7 @@ impl Parser {
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
+                | TokenType::Import
                 | TokenType::While
                 | TokenType::Print
                 | TokenType::Return => {
@@ -669,15 +976,253 @@ impl Display for ClassKind {
     }
 }
 
-fn print_error(token: Token, message: &str) {
-    if token.token_type == TokenType::Eof {
-        report(token.line, "at end", message);
-    } else {
-        report(token.line, &format!("at '{}'", token.lexeme), message);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Result<Vec<Stmt>, Vec<CompileError>> {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .expect("scan error");
+        Parser::new(tokens).parse()
+    }
+
+    fn parse_expression(source: &str) -> Expr {
+        match parse(&format!("{};", source)).unwrap().remove(0) {
+            Stmt {
+                kind: StmtKind::Expression(expr),
+                ..
+            } => expr,
+            stmt => panic!("expected an expression statement, got {:?}", stmt),
+        }
     }
-}
 
-// TODO: dup
-fn report(line_number: usize, location: &str, message: &str) {
-    println!("[line {}] Error {}: {}", line_number, location, message);
+    fn error_messages(source: &str) -> Vec<String> {
+        parse(source)
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn standalone_expression() {
+        let tokens = Scanner::new("1 + 2".to_string()).scan_tokens().unwrap();
+        let expr = Parser::new(tokens).parse_expression().unwrap();
+        assert_eq!(expr.to_string(), "(+ 1 2)");
+
+        let tokens = Scanner::new("1 + 2;".to_string()).scan_tokens().unwrap();
+        let errors = Parser::new(tokens).parse_expression().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error at ';': Expect end of expression."
+        );
+    }
+
+    #[test]
+    fn print_statements() {
+        let statements = parse("fun f(a, b) { if (a) print \"yes\"; else return; }").unwrap();
+
+        assert_eq!(
+            statements[0].to_string(),
+            "(fun f (a b)\n  (if a\n    (print \"yes\")\n    (return)))"
+        );
+    }
+
+    #[test]
+    fn print_calls_and_properties() {
+        assert_eq!(
+            parse_expression("a.b(1, c = 2).d = this").to_string(),
+            "(set (call (get a b) 1 (= c 2)) d this)"
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse_expression("1 + 2 * 3").to_string(), "(+ 1 (* 2 3))");
+        assert_eq!(
+            parse_expression("(1 + 2) * 3").to_string(),
+            "(* (group (+ 1 2)) 3)"
+        );
+        assert_eq!(parse_expression("-1 - -2").to_string(), "(- (- 1) (- 2))");
+        assert_eq!(
+            parse_expression("1 < 2 == true").to_string(),
+            "(== (< 1 2) true)"
+        );
+    }
+
+    #[test]
+    fn logical_operators_bind_looser_than_equality() {
+        assert_eq!(
+            parse_expression("nil or 1 == 2 and false").to_string(),
+            "(or nil (and (== 1 2) false))"
+        );
+    }
+
+    #[test]
+    fn assignment_is_right_associative() {
+        let expr = parse_expression("a = b = 1");
+        match expr.kind {
+            ExprKind::Assign(a, value) => {
+                assert_eq!(a.lexeme, "a");
+                assert!(matches!(value.kind, ExprKind::Assign(ref b, _) if b.lexeme == "b"));
+            }
+            expr => panic!("expected an assignment, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn property_assignment() {
+        assert!(matches!(
+            parse_expression("a.b = 1").kind,
+            ExprKind::Set(..)
+        ));
+    }
+
+    #[test]
+    fn invalid_assignment_target() {
+        assert_eq!(
+            error_messages("1 + 2 = 3;"),
+            vec!["[line 1] Error at '=': Invalid assignment target."]
+        );
+    }
+
+    #[test]
+    fn for_loop_desugars_to_while() {
+        let statements = parse("for (var i = 0; i < 1; i = i + 1) print i;").unwrap();
+        match &statements[0].kind {
+            StmtKind::Block(block) => {
+                assert!(matches!(block[0].kind, StmtKind::Var(..)));
+                assert!(matches!(block[1].kind, StmtKind::While(..)));
+            }
+            statements => panic!("expected a block, got {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn for_loop_clauses_are_optional() {
+        let statements = parse("for (;;) {}").unwrap();
+        assert_eq!(statements.len(), 1);
+        assert!(matches!(statements[0].kind, StmtKind::While(..)));
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        assert_eq!(
+            error_messages("print 1"),
+            vec!["[line 1] Error at end: Expect ';' after value."]
+        );
+    }
+
+    #[test]
+    fn reports_errors_after_synchronizing() {
+        assert_eq!(
+            error_messages("var = 1;\nprint (;\nprint 1;"),
+            vec![
+                "[line 1] Error at '=': Expect variable name.",
+                "[line 2] Error at ';': Expect expression.",
+            ]
+        );
+    }
+
+    #[test]
+    fn argument_limit() {
+        let arguments = vec!["1"; 255].join(", ");
+        assert!(parse(&format!("f({});", arguments)).is_ok());
+
+        let arguments = vec!["1"; 256].join(", ");
+        assert_eq!(
+            error_messages(&format!("f({});", arguments)),
+            vec!["[line 1] Error at '1': Can't have more than 255 arguments."]
+        );
+    }
+
+    #[test]
+    fn parameter_limit() {
+        let parameters: Vec<String> = (0..255).map(|i| format!("p{}", i)).collect();
+        assert!(parse(&format!("fun f({}) {{}}", parameters.join(", "))).is_ok());
+
+        let parameters: Vec<String> = (0..256).map(|i| format!("p{}", i)).collect();
+        assert_eq!(
+            error_messages(&format!("fun f({}) {{}}", parameters.join(", "))),
+            vec!["[line 1] Error at 'p255': Can't have more than 255 parameters."]
+        );
+    }
+
+    #[test]
+    fn empty_token_stream() {
+        assert_eq!(Parser::new(vec![]).parse().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        assert!(parse(&format!("print {}1;", "-".repeat(MAX_DEPTH - 2))).is_ok());
+        assert_eq!(
+            error_messages(&format!("print {}1;", "-".repeat(MAX_DEPTH))),
+            vec!["[line 1] Error at '-': Too much nesting."]
+        );
+
+        // Long operator chains build deep trees too
+        let chain = vec!["1"; MAX_DEPTH + 1].join(" + ");
+        assert_eq!(
+            error_messages(&format!("print {};", chain)),
+            vec!["[line 1] Error at '1': Too much nesting."]
+        );
+    }
+
+    #[test]
+    fn class_with_methods() {
+        let statements = parse("class A { f() {} g(x) { return x; } }").unwrap();
+        match &statements[0].kind {
+            StmtKind::Class(name, methods) => {
+                assert_eq!(name.lexeme, "A");
+                assert_eq!(methods.len(), 2);
+            }
+            statements => panic!("expected a class, got {:?}", statements),
+        }
+    }
+
+    /// Spans as `start_line:start_column-end_line:end_column`.
+    fn span(span: Span) -> String {
+        format!(
+            "{}:{}-{}:{}",
+            span.start_line, span.start_column, span.end_line, span.end_column
+        )
+    }
+
+    #[test]
+    fn statements_and_expressions_have_spans() {
+        let statements = parse("var a = (1 + 2);\nprint \"x\ny\";\n{ a = -a; }").unwrap();
+        let spans: Vec<String> = statements.iter().map(|stmt| span(stmt.span)).collect();
+        assert_eq!(spans, vec!["1:1-1:17", "2:1-3:4", "4:1-4:12"]);
+
+        let StmtKind::Var(_, Some(initializer)) = &statements[0].kind else {
+            panic!("expected a variable declaration");
+        };
+        assert_eq!(span(initializer.span), "1:9-1:16");
+        let ExprKind::Grouping(sum) = &initializer.kind else {
+            panic!("expected a grouping");
+        };
+        assert_eq!(span(sum.span), "1:10-1:15");
+    }
+
+    #[test]
+    fn desugared_for_loop_spans_the_loop() {
+        let statements = parse("for (;;)\n  print 1;").unwrap();
+        let StmtKind::While(_, condition, body) = &statements[0].kind else {
+            panic!("expected a while loop");
+        };
+        assert_eq!(span(statements[0].span), "1:1-2:11");
+        // The missing condition is where it would have been written
+        assert_eq!(span(condition.span), "1:7-1:8");
+        assert_eq!(span(body.span), "2:3-2:11");
+    }
+
+    #[test]
+    fn spans_are_in_the_parsers_file() {
+        let tokens = Scanner::new("print 1;".to_string()).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).in_file(3).parse().unwrap();
+        assert_eq!(statements[0].span.file, 3);
+    }
 }
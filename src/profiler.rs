@@ -0,0 +1,314 @@
+//! Timing where a script spends its time, by function and by line.
+//!
+//! The interpreter tells an attached [`Profiler`] about every call and every
+//! statement as it runs. Time is charged to the statement running when it
+//! passes, so a line's time excludes the calls made from it, as does a
+//! function's self time.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::lox::HOST_LINE;
+
+/// Calls and time spent in one function.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FunctionStats {
+    pub calls: usize,
+    pub total: Duration, // including the calls it made
+    pub own: Duration,   // excluding the calls it made
+}
+
+/// How often a line's statements ran, and for how long.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LineStats {
+    pub hits: usize,
+    pub time: Duration,
+}
+
+struct Call {
+    function: String,
+    start: Instant,
+    children: Duration, // spent in calls it made
+    line: usize,        // of the statement running in it
+}
+
+pub struct Profiler {
+    stack: Vec<Call>,
+    mark: Instant, // when time was last charged to a line
+    functions: HashMap<String, FunctionStats>,
+    lines: HashMap<usize, LineStats>,
+    stacks: HashMap<String, Duration>, // own time by call stack, e.g. `script;fib;fib`
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            stack: vec![Call {
+                function: "script".to_string(),
+                start: now,
+                children: Duration::ZERO,
+                line: HOST_LINE,
+            }],
+            mark: now,
+            functions: HashMap::new(),
+            lines: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    /// Called before each statement runs, with the line it starts on.
+    pub(crate) fn statement(&mut self, line: usize) {
+        self.charge_line();
+        self.lines.entry(line).or_default().hits += 1;
+        if let Some(call) = self.stack.last_mut() {
+            call.line = line;
+        }
+    }
+
+    /// Called as `function` is called.
+    pub(crate) fn enter(&mut self, function: &str) {
+        self.charge_line();
+        self.stack.push(Call {
+            function: function.to_string(),
+            start: Instant::now(),
+            children: Duration::ZERO,
+            line: HOST_LINE,
+        });
+    }
+
+    /// Called as the innermost call returns, or unwinds with an error.
+    pub(crate) fn exit(&mut self) {
+        self.charge_line();
+        // The script's own frame is only finished by `finish`
+        if self.stack.len() > 1 {
+            self.finish_call();
+        }
+    }
+
+    /// Stops timing, charging the time since the last event to where the
+    /// script had got to.
+    pub fn finish(&mut self) {
+        self.charge_line();
+        while !self.stack.is_empty() {
+            self.finish_call();
+        }
+    }
+
+    fn finish_call(&mut self) {
+        let path = self.path();
+        let Some(call) = self.stack.pop() else { return };
+        let elapsed = call.start.elapsed();
+        let own = elapsed.saturating_sub(call.children);
+
+        if let Some(caller) = self.stack.last_mut() {
+            caller.children += elapsed;
+        }
+        *self.stacks.entry(path).or_default() += own;
+
+        // Recursive calls are already counted in the outermost call's total
+        let recursive = self.stack.iter().any(|c| c.function == call.function);
+        let stats = self.functions.entry(call.function).or_default();
+        stats.calls += 1;
+        stats.own += own;
+        if !recursive {
+            stats.total += elapsed;
+        }
+    }
+
+    fn charge_line(&mut self) {
+        let now = Instant::now();
+        if let Some(call) = self.stack.last() {
+            if call.line != HOST_LINE {
+                self.lines.entry(call.line).or_default().time += now - self.mark;
+            }
+        }
+        self.mark = now;
+    }
+
+    /// The functions on the stack, outermost first, separated by `;`.
+    fn path(&self) -> String {
+        let functions: Vec<&str> = self.stack.iter().map(|c| c.function.as_str()).collect();
+        functions.join(";")
+    }
+
+    /// Every function called, most expensive first. The script itself is
+    /// included as `script`, once it's finished.
+    pub fn functions(&self) -> Vec<(&str, FunctionStats)> {
+        let mut functions: Vec<_> = self
+            .functions
+            .iter()
+            .map(|(function, stats)| (function.as_str(), *stats))
+            .collect();
+        functions
+            .sort_by(|(a, a_stats), (b, b_stats)| b_stats.own.cmp(&a_stats.own).then(a.cmp(b)));
+
+        functions
+    }
+
+    /// Every line that ran, most expensive first.
+    pub fn lines(&self) -> Vec<(usize, LineStats)> {
+        let mut lines: Vec<_> = self
+            .lines
+            .iter()
+            .map(|(line, stats)| (*line, *stats))
+            .collect();
+        lines.sort_by(|(a, a_stats), (b, b_stats)| b_stats.time.cmp(&a_stats.time).then(a.cmp(b)));
+
+        lines
+    }
+
+    /// A report of the most expensive functions and lines.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+        let _ = writeln!(
+            report,
+            "{:>8} {:>12} {:>12}  function",
+            "calls", "total ms", "self ms"
+        );
+        for (function, stats) in self.functions() {
+            let _ = writeln!(
+                report,
+                "{:>8} {:>12.3} {:>12.3}  {}",
+                stats.calls,
+                ms(stats.total),
+                ms(stats.own),
+                function
+            );
+        }
+
+        let _ = writeln!(report, "\n{:>8} {:>12}  line", "hits", "time ms");
+        for (line, stats) in self.lines() {
+            let _ = writeln!(
+                report,
+                "{:>8} {:>12.3}  {}",
+                stats.hits,
+                ms(stats.time),
+                line
+            );
+        }
+
+        report
+    }
+
+    /// The time spent in each call stack, in microseconds, in the folded
+    /// format read by flame graph tools such as `flamegraph.pl` and
+    /// `inferno-flamegraph`.
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lox::Lox;
+
+    fn profile(source: &str) -> Profiler {
+        let mut lox = Lox::new();
+        lox.set_output(std::io::sink());
+        lox.set_profiler(Profiler::new());
+        lox.run(source.to_string()).unwrap();
+
+        lox.take_profiler().unwrap()
+    }
+
+    const SOURCE: &str = "\
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+print fib(5);
+";
+
+    #[test]
+    fn counts_calls_and_lines() {
+        let profiler = profile(SOURCE);
+
+        let calls: HashMap<&str, usize> = profiler
+            .functions()
+            .into_iter()
+            .map(|(function, stats)| (function, stats.calls))
+            .collect();
+        assert_eq!(calls, HashMap::from([("fib", 15), ("script", 1)]));
+
+        let hits: HashMap<usize, usize> = profiler
+            .lines()
+            .into_iter()
+            .map(|(line, stats)| (line, stats.hits))
+            .collect();
+        // The return on line 2 runs as a statement of its own
+        assert_eq!(hits, HashMap::from([(1, 1), (2, 23), (3, 7), (5, 1)]));
+    }
+
+    #[test]
+    fn recursion_is_timed_once() {
+        let profiler = profile(SOURCE);
+        let (_, fib) = profiler
+            .functions()
+            .into_iter()
+            .find(|(f, _)| *f == "fib")
+            .unwrap();
+        let (_, script) = profiler
+            .functions()
+            .into_iter()
+            .find(|(f, _)| *f == "script")
+            .unwrap();
+
+        assert!(fib.total <= script.total);
+        assert!(fib.own <= fib.total);
+    }
+
+    #[test]
+    fn folded_stacks() {
+        let folded = profile(SOURCE).folded_stacks();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+
+        assert_eq!(
+            stacks,
+            vec![
+                "script",
+                "script;fib",
+                "script;fib;fib",
+                "script;fib;fib;fib",
+                "script;fib;fib;fib;fib",
+                "script;fib;fib;fib;fib;fib",
+            ]
+        );
+    }
+
+    #[test]
+    fn methods_are_named_by_their_class() {
+        let profiler = profile("class A { m() {} } class B { m() {} } A().m(); B().m(); B().m();");
+
+        let calls: HashMap<&str, usize> = profiler
+            .functions()
+            .into_iter()
+            .map(|(function, stats)| (function, stats.calls))
+            .collect();
+        assert_eq!(
+            calls,
+            HashMap::from([("A", 1), ("A.m", 1), ("B", 2), ("B.m", 2), ("script", 1)])
+        );
+        assert!(profiler.folded_stacks().contains("script;B.m "));
+    }
+}
@@ -0,0 +1,446 @@
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+
+use jlox::error::CompileError;
+use jlox::lox::Lox;
+use jlox::natives;
+use jlox::object::Object;
+use jlox::parser::Parser;
+use jlox::scanner::{Scanner, KEYWORDS};
+use jlox::token::TokenType;
+
+use crate::dump::token_table;
+
+const HISTORY_FILE: &str = ".jlox_history";
+
+/// Runs an interactive session. If `sandbox` is set, code run in it can't
+/// touch files, though `:load` can still read the file it's given.
+pub fn run_prompt(sandbox: bool) {
+    let mut editor: Editor<LoxHelper, DefaultHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("Error starting REPL: {}", err);
+            std::process::exit(74);
+        }
+    };
+    editor.set_helper(Some(LoxHelper::default()));
+
+    let history = history_path();
+    if let Some(history) = &history {
+        // There won't be any history the first time around
+        let _ = editor.load_history(history);
+    }
+
+    let mut lox = session(sandbox);
+
+    loop {
+        match editor.readline("> ") {
+            Ok(line) => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let _ = editor.add_history_entry(line.as_str());
+
+                match line.trim_start().strip_prefix(':') {
+                    Some(command) => run_command(&mut lox, command, sandbox),
+                    None => run_input(&mut lox, line),
+                }
+
+                if let Some(helper) = editor.helper_mut() {
+                    helper.globals = lox.globals();
+                }
+            }
+            // Ctrl-C abandons the current input
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl-D exits
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("Error reading input: {}", err);
+                break;
+            }
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Err(err) = editor.save_history(history) {
+            eprintln!("Error saving history: {}", err);
+        }
+    }
+}
+
+fn session(sandbox: bool) -> Lox {
+    let mut lox = Lox::new();
+    lox.set_file_access(!sandbox);
+    lox
+}
+
+fn run_input(lox: &mut Lox, source: String) {
+    // Errors have already been reported, so just carry on
+    if is_expression(&source) {
+        if let Ok(value) = lox.evaluate(source) {
+            println!("{}", value);
+        }
+    } else {
+        let _ = lox.run(source);
+    }
+}
+
+/// Runs a command such as `:env`, given without its leading colon.
+fn run_command(lox: &mut Lox, command: &str, sandbox: bool) {
+    let (name, argument) = split_command(command);
+
+    match name {
+        "env" => {
+            for (name, value) in lox.globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        "ast" => print_ast(argument),
+        "tokens" => print_tokens(argument),
+        "load" => match std::fs::read_to_string(argument) {
+            Ok(source) => {
+                let _ = lox.run(source);
+            }
+            Err(err) => eprintln!("Error reading '{}': {}", argument, err),
+        },
+        "reset" => *lox = session(sandbox),
+        "time" => {
+            let start = Instant::now();
+            run_input(lox, argument.to_string());
+            println!("Took {:.3?}", start.elapsed());
+        }
+        "help" => println!("{}", HELP),
+        _ => eprintln!("Unknown command ':{}'. Try :help.", name),
+    }
+}
+
+const HELP: &str = "\
+:env           List global variables and their values
+:ast <code>    Show the syntax tree of some code
+:tokens <code> Show the tokens of some code
+:load <file>   Run a file in this session
+:reset         Forget everything defined so far
+:time <code>   Run some code and show how long it took
+:help          Show this message";
+
+/// Splits a command into its name and its (possibly empty) argument.
+fn split_command(command: &str) -> (&str, &str) {
+    match command.trim().split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command.trim(), ""),
+    }
+}
+
+fn print_tokens(source: &str) {
+    match Scanner::new(source.to_string()).scan_tokens() {
+        Ok(tokens) => print!("{}", token_table(&tokens)),
+        Err(errors) => print_errors(&errors),
+    }
+}
+
+fn print_ast(source: &str) {
+    let tokens = match Scanner::new(source.to_string()).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(errors) => return print_errors(&errors),
+    };
+
+    if is_expression(source) {
+        if let Ok(expr) = Parser::new(tokens).parse_expression() {
+            println!("{}", expr);
+        }
+        return;
+    }
+
+    match Parser::new(tokens).parse() {
+        Ok(statements) => {
+            for statement in statements {
+                println!("{}", statement);
+            }
+        }
+        Err(errors) => print_errors(&errors),
+    }
+}
+
+fn print_errors(errors: &[CompileError]) {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::home_dir().map(|home| home.join(HISTORY_FILE))
+}
+
+/// Whether `source` is a bare expression, whose value should be echoed
+/// rather than run as a statement.
+fn is_expression(source: &str) -> bool {
+    Scanner::new(source.to_string())
+        .scan_tokens()
+        .is_ok_and(|tokens| Parser::new(tokens).parse_expression().is_ok())
+}
+
+/// Whether `source` looks like the beginning of a longer input, such as a
+/// block that hasn't been closed yet or a statement that's missing its end.
+fn is_incomplete(source: &str) -> bool {
+    let tokens = match Scanner::new(source.to_string()).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            return errors
+                .iter()
+                .any(|error| error.message == "Unterminated string.")
+        }
+    };
+
+    let depth = tokens
+        .iter()
+        .fold(0isize, |depth, token| match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace => depth + 1,
+            TokenType::RightParen | TokenType::RightBrace => depth - 1,
+            _ => depth,
+        });
+    if depth > 0 {
+        return true;
+    }
+
+    if Parser::new(tokens.clone()).parse_expression().is_ok() {
+        return false;
+    }
+
+    match Parser::new(tokens).parse() {
+        Ok(_) => false,
+        Err(errors) => errors.iter().any(|error| error.location == " at end"),
+    }
+}
+
+/// Whether `c` can be part of a name being completed, including the dots
+/// between an object and its properties.
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+#[derive(Default)]
+struct LoxHelper {
+    /// The session's globals as of the last input. Instances are shared with
+    /// the session, so their fields stay up to date.
+    globals: Vec<(String, Rc<Object>)>,
+}
+
+impl LoxHelper {
+    /// Finds completions for the name ending at `pos`, returning where the
+    /// completed part starts along with the candidates.
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_name_char(*c))
+            .map_or(0, |(index, c)| index + c.len_utf8());
+        let word = &before[start..];
+
+        let (start, prefix, names) = match word.rsplit_once('.') {
+            Some((path, prefix)) => (pos - prefix.len(), prefix, self.property_names(path)),
+            None => {
+                let keywords = KEYWORDS.iter().map(|(keyword, _)| keyword.to_string());
+                let globals = self.globals.iter().map(|(name, _)| name.clone());
+                (start, word, keywords.chain(globals).collect())
+            }
+        };
+
+        let mut candidates: Vec<String> = names
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+
+        (start, candidates)
+    }
+
+    /// The fields and methods of the instance that `path` (e.g. `a.b`)
+    /// refers to, if any.
+    fn property_names(&self, path: &str) -> Vec<String> {
+        let mut names = path.split('.');
+        let object = names.next().and_then(|global| {
+            self.globals
+                .iter()
+                .find(|(name, _)| name == global)
+                .map(|(_, value)| Rc::clone(value))
+        });
+
+        let object = names.try_fold(object, |object, field| match object.as_deref() {
+            Some(Object::Instance(instance)) => Some(instance.borrow().field(field)),
+            _ => None,
+        });
+
+        match object.flatten().as_deref() {
+            Some(Object::Instance(instance)) => instance.borrow().property_names(),
+            Some(value) => natives::method_names(value)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+impl Validator for LoxHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        // Commands may be followed by code, which can span lines too
+        let input = match ctx.input().trim_start().strip_prefix(':') {
+            Some(command) => split_command(command).1,
+            None => ctx.input(),
+        };
+
+        if is_incomplete(input) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_inputs() {
+        assert!(!is_incomplete(""));
+        assert!(!is_incomplete("print 1;"));
+        assert!(!is_incomplete("fun f() { return 1; }"));
+    }
+
+    #[test]
+    fn bare_expressions() {
+        assert!(is_expression("1 + 2"));
+        assert!(is_expression("f(a)"));
+        assert!(!is_expression("print 1;"));
+        assert!(!is_expression("1 + 2;"));
+        assert!(!is_incomplete("1 + 2"));
+    }
+
+    #[test]
+    fn command_arguments() {
+        assert_eq!(split_command("env"), ("env", ""));
+        assert_eq!(split_command(" load  file.lox "), ("load", "file.lox"));
+        assert_eq!(split_command("ast fun f() {\n}"), ("ast", "fun f() {\n}"));
+    }
+
+    #[test]
+    fn unbalanced_delimiters() {
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("class A {\n  f() {\n    return 1;\n  }"));
+    }
+
+    #[test]
+    fn unfinished_statement() {
+        assert!(is_incomplete("var a ="));
+        assert!(is_incomplete("if (true)"));
+    }
+
+    #[test]
+    fn unterminated_string() {
+        assert!(is_incomplete("print \"multi\nline"));
+    }
+
+    fn helper(source: &str) -> LoxHelper {
+        let mut lox = Lox::new();
+        lox.run(source.to_string()).unwrap();
+
+        LoxHelper {
+            globals: lox.globals(),
+        }
+    }
+
+    #[test]
+    fn complete_keywords_and_globals() {
+        let helper = helper("var counter = 1; fun count() {}");
+
+        assert_eq!(
+            helper.candidates("print cou", 9),
+            (6, vec!["count".to_string(), "counter".to_string()])
+        );
+        assert_eq!(helper.candidates("wh", 2), (0, vec!["while".to_string()]));
+        assert_eq!(
+            helper.candidates("cl", 2),
+            (
+                0,
+                vec![
+                    "class".to_string(),
+                    "className".to_string(),
+                    "clock".to_string()
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn complete_properties() {
+        let helper = helper(
+            "class Point { norm() {} }
+             var p = Point();
+             p.x = 1;
+             p.next = Point();
+             p.next.nested = true;",
+        );
+
+        assert_eq!(
+            helper.candidates("p.", 2),
+            (
+                2,
+                vec!["next".to_string(), "norm".to_string(), "x".to_string()]
+            )
+        );
+        assert_eq!(
+            helper.candidates("print p.n", 9),
+            (8, vec!["next".to_string(), "norm".to_string()])
+        );
+        assert_eq!(
+            helper.candidates("p.next.ne", 9),
+            (7, vec!["nested".to_string()])
+        );
+        assert_eq!(helper.candidates("q.", 2), (2, vec![]));
+        assert_eq!(
+            helper.candidates("math.p", 6),
+            (5, vec!["pi".to_string(), "pow".to_string()])
+        );
+    }
+
+    #[test]
+    fn errors_are_not_incomplete() {
+        assert!(!is_incomplete("print );"));
+        assert!(!is_incomplete("}"));
+    }
+}
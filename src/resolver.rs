@@ -1,19 +1,69 @@
 use std::collections::HashMap;
 use std::ops::Deref;
 
+use crate::error::CompileError;
 use crate::interpreter::Interpreter;
 use crate::parser::ClassKind;
-use crate::parser::Expr;
 use crate::parser::FunctionKind;
-use crate::parser::Stmt;
-use crate::token::Token;
-use crate::token::TokenType;
+use crate::parser::{Expr, ExprKind};
+use crate::parser::{Parser, Stmt, StmtKind};
+use crate::scanner::Scanner;
+use crate::token::{Span, Token, MAIN_FILE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Variable,
+    Parameter,
+    Function,
+    Class,
+    Method,
+    Import,
+}
+
+/// A name declared in the program, as the resolver saw it.
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub name: Token,
+    pub file: usize, // the name is in
+    pub kind: DeclarationKind,
+    pub global: bool,
+    pub parent: Option<usize>,  // the function or class it's declared in
+    pub shadows: Option<usize>, // a local of an enclosing scope it hides
+}
+
+/// A name read or assigned, and the local declaration it was resolved to.
+#[derive(Debug, Clone)]
+pub struct Use {
+    pub name: Token,
+    pub file: usize,                // the name is in
+    pub declaration: Option<usize>, // `None` for a global, bound at runtime
+    pub assignment: bool,
+}
+
+/// Every declaration and use of a name in a program and the modules it
+/// imports, in the order the resolver met them. Tools build on these rather
+/// than tracking scopes themselves.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    pub declarations: Vec<Declaration>,
+    pub uses: Vec<Use>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    defined: bool,
+    declaration: Option<usize>, // if symbols are recorded
+}
 
 pub struct Resolver {
     pub interpreter: Interpreter,
-    scopes: Vec<HashMap<String, bool>>, // TODO: HashSet instead?
+    scopes: Vec<HashMap<String, Local>>,
     current_function: Option<FunctionKind>,
     current_class: Option<ClassKind>,
+    file: usize, // being resolved
+    symbols: Option<Symbols>,
+    parent: Option<usize>, // the recorded function or class being resolved
+    errors: Vec<CompileError>,
 }
 
 impl Resolver {
@@ -23,13 +73,29 @@ impl Resolver {
             scopes: vec![],
             current_function: None,
             current_class: None,
+            file: MAIN_FILE,
+            symbols: None,
+            parent: None,
+            errors: vec![],
         }
     }
 
+    /// Records the names the program declares and uses, for
+    /// [`take_symbols`](Self::take_symbols).
+    pub fn recording_symbols(mut self) -> Self {
+        self.symbols = Some(Symbols::default());
+        self
+    }
+
+    /// The names recorded so far, if recording.
+    pub fn take_symbols(&mut self) -> Option<Symbols> {
+        self.symbols.take()
+    }
+
     fn resolve_statement(&mut self, statement: &Stmt) {
-        match statement {
-            Stmt::Var(name, initializer) => {
-                self.declare(name);
+        match &statement.kind {
+            StmtKind::Var(name, initializer) => {
+                self.declare(name, DeclarationKind::Variable);
 
                 if let Some(initializer) = initializer {
                     self.resolve_expression(initializer);
@@ -37,98 +103,115 @@ impl Resolver {
 
                 self.define(name);
             }
-            Stmt::Block(statements) => {
+            StmtKind::Block(statements) => {
                 self.begin_scope();
-                for statement in statements {
-                    self.resolve_statement(statement);
-                }
+                self.resolve_block(statements);
                 self.end_scope();
             }
-            Stmt::Class(name, methods) => self.resolve_class(name, methods, ClassKind::Class),
-            Stmt::If(condition, then_branch, else_branch) => {
+            StmtKind::Class(name, methods) => self.resolve_class(name, methods, ClassKind::Class),
+            StmtKind::If(condition, then_branch, else_branch) => {
                 self.resolve_expression(condition);
                 self.resolve_statement(then_branch);
                 if let Some(else_branch) = else_branch.deref() {
                     self.resolve_statement(else_branch);
                 }
             }
-            Stmt::Expression(expr) => self.resolve_expression(expr),
-            Stmt::Function(name, parameters, body) => {
-                self.declare(&name.lexeme);
-                self.define(&name.lexeme);
+            StmtKind::Expression(expr) => self.resolve_expression(expr),
+            StmtKind::Function(name, parameters, body) => {
+                let declaration = self.declare(name, DeclarationKind::Function);
+                self.define(name);
 
-                self.resolve_function(parameters, body, FunctionKind::Function);
+                self.resolve_function(declaration, parameters, body, FunctionKind::Function);
             }
-            Stmt::Print(expr) => self.resolve_expression(expr),
-            Stmt::Return(keyword, return_value) => {
+            StmtKind::Import(keyword, path, names) => {
+                if self.scopes.is_empty() {
+                    self.resolve_import(statement.span, path, names.as_deref());
+                } else {
+                    self.error(keyword, "Can't import inside a block or function.");
+                }
+            }
+            StmtKind::Print(_keyword, expr) => self.resolve_expression(expr),
+            StmtKind::Return(keyword, return_value) => {
                 if self.current_function.is_none() {
-                    print_error(keyword, "Can't return from top-level code.");
+                    self.error(keyword, "Can't return from top-level code.");
                 }
 
                 if let Some(return_value) = return_value {
                     if let Some(FunctionKind::Initializer) = self.current_function {
-                        print_error(keyword, "Can't return a value from an initializer.");
+                        self.error(keyword, "Can't return a value from an initializer.");
                     }
 
                     self.resolve_expression(return_value);
                 }
             }
-            Stmt::While(condition, body) => {
+            StmtKind::While(_keyword, condition, body) => {
                 self.resolve_expression(condition);
                 self.resolve_statement(body);
             }
         }
     }
 
-    pub fn resolve_statements(&mut self, statements: &[Stmt]) {
+    pub fn resolve_statements(&mut self, statements: &[Stmt]) -> Result<(), Vec<CompileError>> {
+        self.resolve_block(statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_block(&mut self, statements: &[Stmt]) {
         for statement in statements {
             self.resolve_statement(statement);
         }
     }
 
     fn resolve_expression(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Logical(left, _operator, right) => {
+        match &expr.kind {
+            ExprKind::Logical(left, _operator, right) => {
                 self.resolve_expression(left);
                 self.resolve_expression(right);
             }
-            Expr::Binary(left, _operator, right) => {
+            ExprKind::Binary(left, _operator, right) => {
                 self.resolve_expression(left);
                 self.resolve_expression(right);
             }
-            Expr::Call(callee, _paren, arguments) => {
+            ExprKind::Call(callee, _paren, arguments) => {
                 self.resolve_expression(callee);
                 for argument in arguments {
                     self.resolve_expression(argument);
                 }
             }
-            Expr::Grouping(expr) => self.resolve_expression(expr),
-            Expr::Literal(_literal) => {} // no-op
-            Expr::Unary(_operator, right) => self.resolve_expression(right),
-            Expr::Variable(name) => {
+            ExprKind::Grouping(expr) => self.resolve_expression(expr),
+            ExprKind::Literal(_literal) => {} // no-op
+            ExprKind::Unary(_operator, right) => self.resolve_expression(right),
+            ExprKind::Variable(name) => {
                 if let Some(scope) = self.scopes.last() {
-                    if let Some(false) = scope.get(&name.lexeme) {
-                        print_error(name, "Can't read local variable in its own initializer.");
+                    if scope.get(&name.lexeme).is_some_and(|local| !local.defined) {
+                        self.error(name, "Can't read local variable in its own initializer.");
                     }
                 }
 
-                self.resolve_local(expr, name);
+                let declaration = self.resolve_local(expr, name);
+                self.record_use(name, declaration, false);
             }
-            Expr::Assign(identifier, value) => {
+            ExprKind::Assign(identifier, value) => {
                 self.resolve_expression(value);
-                self.resolve_local(value, identifier);
+                let declaration = self.resolve_local(expr, identifier);
+                self.record_use(identifier, declaration, true);
             }
-            Expr::Get(object, _name) => self.resolve_expression(object),
-            Expr::Set(object, _name, value) => {
+            ExprKind::Get(object, _name) => self.resolve_expression(object),
+            ExprKind::Set(object, _name, value) => {
                 self.resolve_expression(value);
                 self.resolve_expression(object);
             }
-            Expr::This(keyword) => {
+            ExprKind::This(keyword) => {
                 if self.current_class.is_none() {
-                    print_error(keyword, "Can't use 'this' outside of a class.");
+                    self.error(keyword, "Can't use 'this' outside of a class.");
                 }
 
-                self.resolve_local(expr, keyword)
+                self.resolve_local(expr, keyword);
             }
         }
     }
@@ -141,95 +224,387 @@ impl Resolver {
         self.scopes.pop();
     }
 
-    fn declare(&mut self, name: &str) {
+    /// Declares `name` in the innermost scope, returning its declaration if
+    /// it was recorded.
+    fn declare(&mut self, name: &Token, kind: DeclarationKind) -> Option<usize> {
+        let declaration = self.record_declaration(name, kind);
+
         if let Some(scope) = self.scopes.last_mut() {
-            if scope.contains_key(name) {
-                eprintln!("Already a variable called '{}' in this scope.", name);
+            let local = Local {
+                defined: false,
+                declaration,
+            };
+            if scope.insert(name.lexeme.clone(), local).is_some() {
+                self.error(name, "Already a variable with this name in this scope.");
             }
-            scope.insert(name.to_string(), false);
         }
+
+        declaration
     }
 
-    fn define(&mut self, name: &str) {
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.to_string(), true);
+    fn define(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.lexeme))
+        {
+            local.defined = true;
+        }
+    }
+
+    /// Records that `name` is declared here, if recording, returning the
+    /// index of its declaration.
+    fn record_declaration(&mut self, name: &Token, kind: DeclarationKind) -> Option<usize> {
+        let symbols = self.symbols.as_mut()?;
+
+        let shadows = match self.scopes.split_last() {
+            Some((_, enclosing)) => enclosing
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&name.lexeme))
+                .and_then(|local| local.declaration),
+            None => None,
+        };
+        symbols.declarations.push(Declaration {
+            name: name.clone(),
+            file: self.file,
+            kind,
+            global: self.scopes.is_empty(),
+            parent: self.parent,
+            shadows,
+        });
+
+        Some(symbols.declarations.len() - 1)
+    }
+
+    fn record_use(&mut self, name: &Token, declaration: Option<usize>, assignment: bool) {
+        if let Some(symbols) = self.symbols.as_mut() {
+            symbols.uses.push(Use {
+                name: name.clone(),
+                file: self.file,
+                declaration,
+                assignment,
+            });
         }
     }
 
-    fn resolve_local(&mut self, expr: &Expr, name: &Token) {
+    /// Loads the module `path` points to, unless it's already loaded, and
+    /// checks it declares the `names` to import from it.
+    fn resolve_import(&mut self, import: Span, path: &Token, names: Option<&[Token]>) {
+        for name in names.unwrap_or_default() {
+            self.record_declaration(name, DeclarationKind::Import);
+        }
+
+        let Some(file) = self.load_module(import.file, path) else {
+            return;
+        };
+        self.interpreter.modules.record_import(import, file);
+
+        let module = self.interpreter.modules.get(file);
+        let missing: Vec<(&Token, String)> = names
+            .unwrap_or_default()
+            .iter()
+            .filter(|name| !module.names.contains(&name.lexeme))
+            .map(|name| {
+                let message = format!("Module '{}' has no '{}'.", module.name, name.lexeme);
+                (name, message)
+            })
+            .collect();
+        for (name, message) in missing {
+            self.error(name, &message);
+        }
+    }
+
+    /// Finds and compiles the module at `path`, as imported from `file`,
+    /// returning its file id. Errors are reported at `path`.
+    fn load_module(&mut self, file: usize, path: &Token) -> Option<usize> {
+        let name = path.literal.to_string();
+        // Modules are files, which a sandboxed script can't read
+        if !self.interpreter.file_access() {
+            self.error(path, "Can't import modules when file access is disabled.");
+            return None;
+        }
+
+        let located = self.interpreter.modules.locate(file, &name);
+        let loaded = located
+            .canonicalize()
+            .and_then(|canonical| Ok((std::fs::read_to_string(&canonical)?, canonical)));
+        let (source, canonical) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                self.error(path, &format!("Can't read module '{}': {}.", name, err));
+                return None;
+            }
+        };
+
+        if let Some(file) = self.interpreter.modules.find(&canonical) {
+            if let Some(cycle) = self.interpreter.modules.cycle(file) {
+                self.error(path, &format!("Import cycle: {}.", cycle));
+                return None;
+            }
+            return Some(file);
+        }
+
+        let file = self.interpreter.modules.begin(name.clone(), canonical);
+        match self.compile_module(file, source) {
+            Ok(statements) => {
+                self.interpreter.modules.finish(file, Some(statements));
+                Some(file)
+            }
+            Err(errors) => {
+                self.interpreter.modules.finish(file, None);
+                for error in errors {
+                    self.error(path, &format!("In module '{}': {}", name, error));
+                }
+                None
+            }
+        }
+    }
+
+    /// Scans, parses and resolves the module in `file`, as though it were a
+    /// script of its own.
+    fn compile_module(
+        &mut self,
+        file: usize,
+        source: String,
+    ) -> Result<Vec<Stmt>, Vec<CompileError>> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        let statements = Parser::new(tokens).in_file(file).parse()?;
+
+        let errors = std::mem::take(&mut self.errors);
+        let importer = std::mem::replace(&mut self.file, file);
+        self.resolve_block(&statements);
+        self.file = importer;
+        let module_errors = std::mem::replace(&mut self.errors, errors);
+
+        if module_errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(module_errors)
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(CompileError::at_token(token, message));
+    }
+
+    /// Resolves `name` to the innermost local declaring it, if any, returning
+    /// that local's declaration if it was recorded.
+    fn resolve_local(&mut self, expr: &Expr, name: &Token) -> Option<usize> {
         for (i, scope) in self.scopes.iter().enumerate().rev() {
-            if scope.contains_key(&name.lexeme) {
+            if let Some(local) = scope.get(&name.lexeme) {
+                let declaration = local.declaration;
                 let depth = self.scopes.len() - 1 - i;
                 self.interpreter.resolve(expr, depth);
-                return;
+                return declaration;
             }
         }
+
+        None
     }
 
-    fn resolve_function(&mut self, params: &[Token], body: &[Stmt], kind: FunctionKind) {
+    fn resolve_function(
+        &mut self,
+        declaration: Option<usize>,
+        params: &[Token],
+        body: &[Stmt],
+        kind: FunctionKind,
+    ) {
         // Store current_function for later
-        let previous = std::mem::replace(&mut self.current_function, Some(kind));
+        let previous = self.current_function.replace(kind);
+        let parent = std::mem::replace(&mut self.parent, declaration);
 
         self.begin_scope();
 
         for param in params {
-            self.declare(&param.lexeme);
-            self.define(&param.lexeme);
+            self.declare(param, DeclarationKind::Parameter);
+            self.define(param);
         }
 
-        self.resolve_statements(body);
+        self.resolve_block(body);
         self.end_scope();
 
+        self.parent = parent;
         // Restore previous current_function
         let _ = std::mem::replace(&mut self.current_function, previous);
     }
 
     fn resolve_class(&mut self, name: &Token, methods: &[Stmt], kind: ClassKind) {
         // Store current_class for later
-        let previous = std::mem::replace(&mut self.current_class, Some(kind));
+        let previous = self.current_class.replace(kind);
 
-        self.declare(&name.lexeme);
-        self.define(&name.lexeme);
+        let class = self.declare(name, DeclarationKind::Class);
+        self.define(name);
+        let parent = std::mem::replace(&mut self.parent, class);
 
         // Define `this` in all methods
         self.begin_scope();
+        let this = Local {
+            defined: true,
+            declaration: None,
+        };
         self.scopes
             .last_mut()
             .unwrap() // Guaranteed to exist because we just began a new scope
-            .insert("this".to_string(), true);
+            .insert("this".to_string(), this);
 
         // TODO: Can we push (name, params, body) into `methods` in `Parser`
         // so we don't need to check if the method is actually a Function Stmt?
         for method in methods {
-            if let Stmt::Function(func_name, parameters, body) = method {
+            if let StmtKind::Function(func_name, parameters, body) = &method.kind {
                 let declaration = if func_name.lexeme.eq("init") {
                     FunctionKind::Initializer
                 } else {
                     FunctionKind::Method
                 };
 
-                self.resolve_function(parameters, body, declaration);
+                let method = self.record_declaration(func_name, DeclarationKind::Method);
+                self.resolve_function(method, parameters, body, declaration);
             } else {
-                print_error(name, "Method wasn't a function.");
+                self.error(name, "Method wasn't a function.");
             }
         }
 
         self.end_scope();
 
+        self.parent = parent;
         // Restore previous current_class
         let _ = std::mem::replace(&mut self.current_class, previous);
     }
 }
 
-fn print_error(token: &Token, message: &str) {
-    if token.token_type == TokenType::Eof {
-        report(token.line, "at end", message);
-    } else {
-        report(token.line, &format!("at '{}'", token.lexeme), message);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn resolve(source: &str) -> Result<(), Vec<String>> {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .expect("scan error");
+        let statements = Parser::new(tokens).parse().expect("parse error");
+
+        Resolver::new(Interpreter::new())
+            .resolve_statements(&statements)
+            .map_err(|errors| errors.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn valid_program() {
+        let source = "
+            var a = 1;
+            fun f(b) { var c = a + b; return c; }
+            class C { init() { this.x = 1; } get() { return this.x; } }
+        ";
+        assert_eq!(resolve(source), Ok(()));
+    }
+
+    #[test]
+    fn globals_can_be_redeclared() {
+        assert_eq!(resolve("var a = 1; var a = 2;"), Ok(()));
+    }
+
+    #[test]
+    fn duplicate_local() {
+        assert_eq!(
+            resolve("{ var a; var a; }"),
+            Err(vec![
+                "[line 1] Error at 'a': Already a variable with this name in this scope.".into()
+            ])
+        );
+    }
+
+    #[test]
+    fn read_in_own_initializer() {
+        assert_eq!(
+            resolve("{ var a = a; }"),
+            Err(vec![
+                "[line 1] Error at 'a': Can't read local variable in its own initializer.".into()
+            ])
+        );
     }
-}
 
-// TODO: dup
-fn report(line_number: usize, location: &str, message: &str) {
-    println!("[line {}] Error {}: {}", line_number, location, message);
+    #[test]
+    fn return_outside_function() {
+        assert_eq!(
+            resolve("return;"),
+            Err(vec![
+                "[line 1] Error at 'return': Can't return from top-level code.".into()
+            ])
+        );
+    }
+
+    #[test]
+    fn return_value_from_initializer() {
+        assert_eq!(
+            resolve("class A { init() { return 1; } }"),
+            Err(vec![
+                "[line 1] Error at 'return': Can't return a value from an initializer.".into()
+            ])
+        );
+        assert_eq!(resolve("class A { init() { return; } }"), Ok(()));
+    }
+
+    #[test]
+    fn this_outside_class() {
+        assert_eq!(
+            resolve("fun f() { return this; }"),
+            Err(vec![
+                "[line 1] Error at 'this': Can't use 'this' outside of a class.".into()
+            ])
+        );
+    }
+
+    #[test]
+    fn records_symbols() {
+        let source = "var a;\nfun f(b) {\n  { var a = b; a = 1; }\n  return a;\n}";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut resolver = Resolver::new(Interpreter::new()).recording_symbols();
+        resolver.resolve_statements(&statements).unwrap();
+        let symbols = resolver.take_symbols().unwrap();
+
+        let declarations: Vec<(&str, DeclarationKind, bool, Option<usize>)> = symbols
+            .declarations
+            .iter()
+            .map(|declaration| {
+                let name = declaration.name.lexeme.as_str();
+                (
+                    name,
+                    declaration.kind,
+                    declaration.global,
+                    declaration.shadows,
+                )
+            })
+            .collect();
+        assert_eq!(
+            declarations,
+            vec![
+                ("a", DeclarationKind::Variable, true, None),
+                ("f", DeclarationKind::Function, true, None),
+                ("b", DeclarationKind::Parameter, false, None),
+                ("a", DeclarationKind::Variable, false, None),
+            ]
+        );
+
+        // Globals are left to be bound at runtime
+        let uses: Vec<(usize, Option<usize>, bool)> = symbols
+            .uses
+            .iter()
+            .map(|name| (name.name.line, name.declaration, name.assignment))
+            .collect();
+        assert_eq!(
+            uses,
+            vec![(3, Some(2), false), (3, Some(3), true), (4, None, false)]
+        );
+    }
+
+    #[test]
+    fn collects_every_error() {
+        assert_eq!(
+            resolve("return;\nprint this;").map_err(|errors| errors.len()),
+            Err(2)
+        );
+    }
 }
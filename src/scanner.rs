@@ -2,40 +2,51 @@ use std::collections::HashMap;
 
 use ordered_float::OrderedFloat;
 
+use crate::error::CompileError;
 use crate::token::{Literal, Token, TokenType};
 
+/// Reserved words and the tokens they scan to.
+pub const KEYWORDS: [(&str, TokenType); 17] = [
+    ("and", TokenType::And),
+    ("class", TokenType::Class),
+    ("else", TokenType::Else),
+    ("false", TokenType::False),
+    ("for", TokenType::For),
+    ("fun", TokenType::Fun),
+    ("if", TokenType::If),
+    ("import", TokenType::Import),
+    ("nil", TokenType::Nil),
+    ("or", TokenType::Or),
+    ("print", TokenType::Print),
+    ("return", TokenType::Return),
+    ("super", TokenType::Super),
+    ("this", TokenType::This),
+    ("true", TokenType::True),
+    ("var", TokenType::Var),
+    ("while", TokenType::While),
+];
+
 #[derive(Default)]
 pub struct Scanner {
     source: Vec<char>,
     tokens: Vec<Token>,
     line_number: usize,
+    line_start: usize, // index of the first character on the current line
     start: usize,
+    start_line: usize,
+    start_column: usize,
     current: usize,
-    had_error: bool,
+    errors: Vec<CompileError>,
+    comments: Vec<Token>,
     keywords: HashMap<String, TokenType>, // TODO: make static
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
-        let keywords = [
-            ("and".to_string(), TokenType::And),
-            ("class".to_string(), TokenType::Class),
-            ("else".to_string(), TokenType::Else),
-            ("false".to_string(), TokenType::False),
-            ("for".to_string(), TokenType::For),
-            ("fun".to_string(), TokenType::Fun),
-            ("if".to_string(), TokenType::If),
-            ("nil".to_string(), TokenType::Nil),
-            ("or".to_string(), TokenType::Or),
-            ("print".to_string(), TokenType::Print),
-            ("return".to_string(), TokenType::Return),
-            ("super".to_string(), TokenType::Super),
-            ("this".to_string(), TokenType::This),
-            ("true".to_string(), TokenType::True),
-            ("var".to_string(), TokenType::Var),
-            ("while".to_string(), TokenType::While),
-        ]
-        .into();
+        let keywords = KEYWORDS
+            .iter()
+            .map(|(keyword, token_type)| (keyword.to_string(), *token_type))
+            .collect();
 
         Self {
             source: source.chars().collect(),
@@ -49,10 +60,16 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
-    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, ()> {
-        // let chars: Vec<char> = self.source.chars().collect();
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<CompileError>> {
+        // Skip a shebang line, so scripts can be run directly
+        if self.source.starts_with(&['#', '!']) {
+            while self.peek().is_some_and(|c| *c != '\n') {
+                self.advance();
+            }
+        }
+
         while !self.is_at_end() {
-            self.start = self.current;
+            self.mark_start();
 
             match self.advance() {
                 '(' => self.add_token(TokenType::LeftParen),
@@ -95,36 +112,54 @@ impl Scanner {
                 }
                 '/' => {
                     if self.match_('/') {
-                        while self.peek().map_or(false, |c| *c != '\n') {
+                        while self.peek().is_some_and(|c| *c != '\n') {
                             self.advance();
                         }
+                        self.add_comment();
                     } else {
                         self.add_token(TokenType::Slash);
                     }
                 }
                 ' ' | '\r' | '\t' => {}
-                '\n' => self.line_number += 1,
+                '\n' => self.new_line(),
                 '"' => self.string(),
                 '0'..='9' => self.digit(),
                 'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
-                _ => {
-                    print_error(self.line_number, "Unexpected character.");
-                    self.had_error = true;
-                }
+                _ => self.error("Unexpected character."),
             };
         }
 
+        self.mark_start();
         self.add_token(TokenType::Eof);
 
-        if self.had_error {
-            Err(())
+        if !self.errors.is_empty() {
+            Err(self.errors.clone())
         } else {
             Ok(self.tokens.clone())
         }
     }
 
+    /// Starts a new token at the current character.
+    fn mark_start(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line_number;
+        self.start_column = self.current - self.line_start + 1;
+    }
+
+    /// Moves on to the next line, having just consumed a newline.
+    fn new_line(&mut self) {
+        self.line_number += 1;
+        self.line_start = self.current;
+    }
+
+    fn error(&mut self, message: &str) {
+        self.errors
+            .push(CompileError::new(self.line_number, message));
+    }
+
     fn advance(&mut self) -> char {
-        let c = self.source[self.current];
+        // Callers check `is_at_end` first, so the fallback is never scanned
+        let c = self.peek().copied().unwrap_or('\0');
         self.current += 1;
         c
     }
@@ -139,14 +174,33 @@ impl Scanner {
             token_type,
             text.iter().collect(),
             literal,
-            self.line_number,
-            self.start,
+            self.start_line,
+            self.start_column,
         );
         self.tokens.push(token);
     }
 
+    /// Sets aside the comment just scanned, so that the parser never sees it
+    /// but tools that reproduce the source can.
+    fn add_comment(&mut self) {
+        let text: String = self.source[self.start..self.current].iter().collect();
+        let comment = Token::new(
+            TokenType::Comment,
+            text,
+            Literal::None,
+            self.start_line,
+            self.start_column,
+        );
+        self.comments.push(comment);
+    }
+
+    /// The comments found by [`Scanner::scan_tokens`], in order.
+    pub fn comments(&self) -> &[Token] {
+        &self.comments
+    }
+
     fn match_(&mut self, expected: char) -> bool {
-        if self.is_at_end() || self.source[self.current] != expected {
+        if self.peek() != Some(&expected) {
             false
         } else {
             self.current += 1;
@@ -164,19 +218,20 @@ impl Scanner {
 
     fn string(&mut self) {
         // Chomp until we reach the closing quote or the end of the input
-        while let Some(c) = self.peek() {
-            match *c {
-                '"' => break,
-                '\n' => self.line_number += 1,
-                _ => {}
+        while let Some(&c) = self.peek() {
+            if c == '"' {
+                break;
             }
 
             self.advance();
+            if c == '\n' {
+                self.new_line();
+            }
         }
 
         // Raise an error if the string was unterminated
         if self.is_at_end() {
-            print_error(self.line_number, "Unterminated string");
+            self.error("Unterminated string.");
             return;
         }
 
@@ -209,8 +264,10 @@ impl Scanner {
 
         // Convert string representation into an f64
         let value: String = self.source[self.start..self.current].iter().collect();
-        let value: OrderedFloat<f64> = value.parse().expect("must be numeric");
-        self.add_token_with_literal(TokenType::Number, Literal::Number(value));
+        match value.parse::<OrderedFloat<f64>>() {
+            Ok(value) => self.add_token_with_literal(TokenType::Number, Literal::Number(value)),
+            Err(_) => self.error("Invalid number."),
+        }
     }
 
     fn identifier(&mut self) {
@@ -224,10 +281,155 @@ impl Scanner {
     }
 }
 
-fn print_error(line_number: usize, message: &str) {
-    report(line_number, "", message);
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Result<Vec<Token>, Vec<CompileError>> {
+        Scanner::new(source.to_string()).scan_tokens()
+    }
+
+    fn token_types(source: &str) -> Vec<TokenType> {
+        scan(source)
+            .expect("scan error")
+            .iter()
+            .map(|token| token.token_type)
+            .collect()
+    }
+
+    #[test]
+    fn empty_source_is_just_eof() {
+        assert_eq!(token_types(""), vec![TokenType::Eof]);
+    }
+
+    #[test]
+    fn eof_is_after_the_last_character() {
+        let tokens = scan("print 1;").expect("scan error");
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.token_type, TokenType::Eof);
+        assert_eq!(eof.lexeme, "");
+        assert_eq!((eof.line, eof.column), (1, 9));
+    }
+
+    #[test]
+    fn one_and_two_character_operators() {
+        assert_eq!(
+            token_types("! != = == < <= > >= /"),
+            vec![
+                TokenType::Bang,
+                TokenType::BangEqual,
+                TokenType::Equal,
+                TokenType::EqualEqual,
+                TokenType::Less,
+                TokenType::LessEqual,
+                TokenType::Greater,
+                TokenType::GreaterEqual,
+                TokenType::Slash,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_and_identifiers() {
+        assert_eq!(
+            token_types("class classy _var var"),
+            vec![
+                TokenType::Class,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Var,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(
+            token_types("1 // a comment\n2"),
+            vec![TokenType::Number, TokenType::Number, TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn comments_are_kept_aside() {
+        let mut scanner = Scanner::new("1 // one\n  // two\n2".to_string());
+        scanner.scan_tokens().unwrap();
+
+        let comments: Vec<(&str, usize, usize)> = scanner
+            .comments()
+            .iter()
+            .map(|comment| (comment.lexeme.as_str(), comment.line, comment.column))
+            .collect();
+        assert_eq!(comments, vec![("// one", 1, 3), ("// two", 2, 3)]);
+    }
+
+    #[test]
+    fn shebang_line_is_skipped() {
+        let tokens = scan("#!/usr/bin/env jlox\nnil").unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::Nil);
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn number_with_fraction() {
+        let tokens = scan("1.5").unwrap();
+        assert_eq!(tokens[0].literal, Literal::Number(1.5.into()));
+        assert_eq!(tokens[0].lexeme, "1.5");
+    }
 
-fn report(line_number: usize, location: &str, message: &str) {
-    println!("[line {}] Error {}: {}", line_number, location, message);
+    #[test]
+    fn trailing_dot_is_not_part_of_number() {
+        let tokens = scan("1.").unwrap();
+        assert_eq!(tokens[0].literal, Literal::Number(1.0.into()));
+        assert_eq!(tokens[1].token_type, TokenType::Dot);
+    }
+
+    #[test]
+    fn string_literal() {
+        let tokens = scan("\"hello\"").unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::String);
+        assert_eq!(tokens[0].lexeme, "\"hello\"");
+        assert_eq!(tokens[0].literal, Literal::String("hello".into()));
+    }
+
+    #[test]
+    fn multiline_string_advances_line() {
+        let tokens = scan("\"a\nb\" x").unwrap();
+        assert_eq!(tokens[0].literal, Literal::String("a\nb".into()));
+        assert_eq!(tokens[1].line, 2);
+    }
+
+    #[test]
+    fn tokens_start_at_their_column() {
+        let tokens = scan("var a\n  = \"b\nc\" d;").unwrap();
+        let positions: Vec<(usize, usize)> = tokens
+            .iter()
+            .map(|token| (token.line, token.column))
+            .collect();
+
+        assert_eq!(
+            positions,
+            vec![(1, 1), (1, 5), (2, 3), (2, 5), (3, 4), (3, 5), (3, 6)]
+        );
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let errors = scan("\"abc\n").unwrap_err();
+        assert_eq!(errors, vec![CompileError::new(2, "Unterminated string.")]);
+    }
+
+    #[test]
+    fn reports_every_unexpected_character() {
+        let errors = scan("@\n#").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                CompileError::new(1, "Unexpected character."),
+                CompileError::new(2, "Unexpected character."),
+            ]
+        );
+    }
 }
@@ -4,11 +4,11 @@ use ordered_float::OrderedFloat;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Token {
-    pub(crate) token_type: TokenType,
-    pub(crate) lexeme: String,
-    pub(crate) literal: Literal,
-    pub(crate) line: usize,
-    pub(crate) column: usize,
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub literal: Literal,
+    pub line: usize,
+    pub column: usize,
 }
 
 impl Token {
@@ -27,6 +27,51 @@ impl Token {
             column,
         }
     }
+
+    /// Where the token is in the source.
+    pub fn span(&self, file: usize) -> Span {
+        // Only strings can span lines, ending on the line of their last newline
+        let (end_line, end_column) = match self.lexeme.rsplit_once('\n') {
+            Some((before, last)) => (
+                self.line + before.matches('\n').count() + 1,
+                last.chars().count() + 1,
+            ),
+            None => (self.line, self.column + self.lexeme.chars().count()),
+        };
+
+        Span {
+            file,
+            start_line: self.line,
+            start_column: self.column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+/// The source file scripts are run from, as opposed to one they import.
+pub const MAIN_FILE: usize = 0;
+
+/// A stretch of source code, from its first character up to but not
+/// including `end_column` on `end_line`. Lines and columns count from 1.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub file: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// The span from the start of this one to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end_line: other.end_line,
+            end_column: other.end_column,
+            ..self
+        }
+    }
 }
 
 impl Display for Token {
@@ -100,6 +145,7 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
@@ -110,5 +156,8 @@ pub enum TokenType {
     Var,
     While,
 
+    // Kept aside by the scanner, rather than passed to the parser.
+    Comment,
+
     Eof,
 }
@@ -0,0 +1,477 @@
+//! Checks the command-line interface of the `jlox` binary: how scripts are
+//! given to it, what they can see of their arguments, and its exit codes.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+fn jlox(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jlox"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("error running jlox");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+
+    child.wait_with_output().expect("error running jlox")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// A directory for the files of one test, removed with them once the test is
+/// done.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(test: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("jlox-cli-{}-{}", std::process::id(), test));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes the file `name` in the directory, returning its path.
+    fn write(&self, name: &str, contents: &str) -> String {
+        let path = self.0.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes a script called `name` in a directory of its own, which is removed
+/// when the returned `TempDir` is dropped.
+fn script(name: &str, source: &str) -> (TempDir, String) {
+    let directory = TempDir::new(name);
+    let path = directory.write(&format!("{}.lox", name), source);
+    (directory, path)
+}
+
+#[test]
+fn evaluate_code() {
+    let output = jlox(&["-e", "print 1 + 2;"], "");
+    assert_eq!(stdout(&output), "3\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn script_arguments() {
+    let (_directory, path) = script("args", "print args();");
+
+    assert_eq!(stdout(&jlox(&[&path, "a", "-b"], "")), "[a, -b]\n");
+    assert_eq!(stdout(&jlox(&["run", &path, "c"], "")), "[c]\n");
+    assert_eq!(stdout(&jlox(&["-e", "print args();", "d"], "")), "[d]\n");
+}
+
+#[test]
+fn script_from_stdin() {
+    assert_eq!(stdout(&jlox(&["-"], "print \"in\";")), "in\n");
+    assert_eq!(stdout(&jlox(&["run", "-"], "print \"run\";")), "run\n");
+}
+
+#[test]
+fn shebang_line_is_skipped() {
+    let output = jlox(&["-"], "#!/usr/bin/env jlox\nprint 1;\nprint;");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr, "[line 3] Error at ';': Expect expression.\n");
+}
+
+#[test]
+fn check_does_not_run() {
+    let output = jlox(&["check", "-"], "print 1;");
+    assert_eq!(stdout(&output), "");
+    assert!(output.status.success());
+
+    let output = jlox(&["check", "-"], "return 1;");
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn exit_codes() {
+    assert_eq!(jlox(&["-e", "print nil + 1;"], "").status.code(), Some(70));
+    assert_eq!(jlox(&["--bogus"], "").status.code(), Some(64));
+    assert_eq!(jlox(&["no/such/file.lox"], "").status.code(), Some(66));
+    assert_eq!(jlox(&["--version"], "").status.code(), Some(0));
+}
+
+#[test]
+fn format_scripts() {
+    let output = jlox(&["fmt", "-"], "print  1 ;");
+    assert_eq!(stdout(&output), "print 1;\n");
+
+    let (_directory, path) = script("fmt", "if(true){print 1;}");
+    assert_eq!(jlox(&["fmt", "--check", &path], "").status.code(), Some(1));
+    assert!(jlox(&["fmt", &path], "").status.success());
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "if (true) {\n  print 1;\n}\n"
+    );
+    assert!(jlox(&["fmt", "--check", &path], "").status.success());
+}
+
+#[test]
+fn lint_scripts() {
+    let (_directory, path) = script("lint", "{\n  var unused = 1;\n}\n");
+
+    let output = jlox(&["lint", &path], "");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr,
+        format!(
+            "{}:2:7: warning: Local variable 'unused' is never used. [unused-variable]\n\
+             2 |   var unused = 1;\n\
+             \x20 |       ^^^^^^\n",
+            path
+        )
+    );
+
+    let output = jlox(&["lint", "--allow", "unused-variable", &path], "");
+    assert!(output.status.success());
+}
+
+#[test]
+fn language_server() {
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#,
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.lox","languageId":"lox","version":1,"text":"print ;"}}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ];
+    let input: String = messages
+        .iter()
+        .map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message))
+        .collect();
+
+    let output = jlox(&["lsp"], &input);
+    let stdout = stdout(&output);
+    assert!(stdout.contains(r#""definitionProvider":true"#));
+    assert!(stdout.contains(r#""message":"Expect expression.""#));
+    assert!(stdout.contains(r#""id":2,"result":null"#));
+    assert!(output.status.success());
+}
+
+#[test]
+fn debug_a_script() {
+    let (_directory, path) = script(
+        "debug",
+        "fun add(a, b) {\n  var sum = a + b;\n  return sum;\n}\nprint add(1, 2);\n",
+    );
+
+    let output = jlox(&["debug", "-b", "3", &path], "p sum * 10\nbt\nfin\nc\n");
+    assert_eq!(
+        stdout(&output),
+        "add(), line 3\n   3 |   return sum;\n(jlox) 30\n\
+         (jlox) > #0 add(), line 3\n  #1 script, line 5\n\
+         (jlox) 3\n"
+    );
+    assert!(output.status.success());
+}
+
+/// The requests a Debug Adapter Protocol client would send, framed for the
+/// adapter's standard input.
+fn dap_requests(requests: &[(&str, serde_json::Value)]) -> String {
+    requests
+        .iter()
+        .enumerate()
+        .map(|(seq, (command, arguments))| {
+            let request = serde_json::json!({
+                "seq": seq + 1,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            format!("Content-Length: {}\r\n\r\n{}", request.len(), request)
+        })
+        .collect()
+}
+
+#[test]
+fn debug_adapter() {
+    let (_directory, path) = script(
+        "dap",
+        "fun add(a, b) {\n  var sum = a + b;\n  return sum;\n}\nprint add(1, 2);\n",
+    );
+    let requests = [
+        ("initialize", serde_json::json!({ "adapterID": "jlox" })),
+        ("launch", serde_json::json!({ "program": path })),
+        (
+            "setBreakpoints",
+            serde_json::json!({ "source": { "path": "other.lox" }, "breakpoints": [{ "line": 1 }] }),
+        ),
+        (
+            "setBreakpoints",
+            serde_json::json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }] }),
+        ),
+        ("configurationDone", serde_json::json!({})),
+        ("stackTrace", serde_json::json!({ "threadId": 1 })),
+        ("scopes", serde_json::json!({ "frameId": 2 })),
+        ("variables", serde_json::json!({ "variablesReference": 1 })),
+        ("continue", serde_json::json!({ "threadId": 1 })),
+    ];
+    let output = jlox(&["dap"], &dap_requests(&requests));
+    let stdout = stdout(&output);
+    assert!(stdout.contains(r#""breakpoints":[{"verified":false,"line":1}]"#));
+    assert!(stdout.contains(r#""breakpoints":[{"verified":true,"line":3}]"#));
+    assert!(stdout.contains(r#""event":"stopped","body":{"reason":"breakpoint""#));
+    assert!(stdout.contains(r#""id":2,"name":"add""#));
+    assert!(stdout.contains(r#"{"name":"sum","value":"3","variablesReference":0}"#));
+    assert!(stdout.contains(r#""body":{"category":"stdout","output":"3\n"}"#));
+    assert!(stdout.contains(r#""event":"exited","body":{"exitCode":0}"#));
+}
+
+#[test]
+fn profile_a_script() {
+    let (_directory, path) = script(
+        "profile",
+        "fun fib(n) {\n  if (n < 2) return n;\n  return fib(n - 1) + fib(n - 2);\n}\nprint fib(3);\n",
+    );
+    let folded = format!("{}.folded", path);
+
+    let output = jlox(&[&format!("--profile={}", folded), &path], "");
+    assert_eq!(stdout(&output), "2\n");
+    let report = String::from_utf8_lossy(&output.stderr);
+    assert!(report
+        .lines()
+        .any(|line| line.trim_start().starts_with("5 ") && line.ends_with("fib")));
+
+    let stacks: Vec<String> = std::fs::read_to_string(&folded)
+        .unwrap()
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+        .collect();
+    assert_eq!(
+        stacks,
+        vec![
+            "script",
+            "script;fib",
+            "script;fib;fib",
+            "script;fib;fib;fib"
+        ]
+    );
+}
+
+#[test]
+fn cover_a_script() {
+    let (_directory, path) = script("coverage", "var a = 1;\nif (a > 1) {\n  print a;\n}\n");
+    let lcov = format!("{}.info", path);
+
+    let output = jlox(&[&format!("--coverage={}", lcov), &path], "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Lines: 2/3 (66.7%)\nBranches: 1/2 (50.0%)\nLines not run: 3\n"
+    );
+
+    let lcov = std::fs::read_to_string(&lcov).unwrap();
+    assert!(lcov.starts_with(&format!("TN:\nSF:{}\n", path)));
+    assert!(lcov.contains("BRDA:2,0,0,0\nBRDA:2,0,1,1\n"));
+    assert!(lcov.contains("DA:1,1\nDA:2,1\nDA:3,0\n"));
+}
+
+#[test]
+fn cover_a_script_with_modules() {
+    let directory = TempDir::new("cover");
+    let sign = "fun sign(n) {\n  if (n < 0) return -1;\n  return 1;\n}\n";
+    directory.write("sign.lox", sign);
+    let main = directory.write(
+        "main.lox",
+        "import { sign } from \"sign.lox\";\nprint sign(1);\n",
+    );
+
+    // Only the script is covered, so the branch in the module isn't counted
+    let lcov = directory.path().join("main.info");
+    let output = jlox(
+        &[&format!("--coverage={}", lcov.to_string_lossy()), &main],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Lines: 2/2 (100.0%)\nBranches: 0/0 (100.0%)\n"
+    );
+    let lcov = std::fs::read_to_string(&lcov).unwrap();
+    assert!(!lcov.contains("BRDA"), "{}", lcov);
+}
+
+#[test]
+fn import_modules() {
+    let directory = TempDir::new("modules");
+    let counter = "print \"loading\";\nvar count = 0;\nfun bump() {\n  count = count + 1;\n  return count;\n}\n";
+    directory.write("counter.lox", counter);
+    let main = "var count = 100;\nimport { bump } from \"counter.lox\";\nimport \"counter.lox\";\nprint bump();\nprint bump();\nprint count;\n";
+    let main = directory.write("main.lox", main);
+
+    // The module runs once, and keeps its own `count`, though importing it
+    // whole copies its `count` over the script's
+    let output = jlox(&[&main], "");
+    assert_eq!(stdout(&output), "loading\n1\n2\n0\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn file_natives() {
+    let (scratch, path) = script(
+        "files",
+        "var directory = args().get(0);
+         var path = directory + \"/notes.txt\";
+         mkdir(directory + \"/sub\");
+         writeFile(path, \"one\");
+         appendFile(path, \" two\");
+         print readFile(path);
+         print readLines(path);
+         print listDir(directory);
+         remove(path);
+         remove(directory + \"/sub\");
+         print exists(path);
+         print listDir(directory);
+         readFile(path);",
+    );
+    let directory = scratch.path().join("work");
+
+    let output = jlox(&[&path, &directory.to_string_lossy()], "");
+    assert_eq!(
+        stdout(&output),
+        "one two\n[one two]\n[notes.txt, sub]\nfalse\n[]\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Can't read '"), "{}", stderr);
+    assert_eq!(output.status.code(), Some(70));
+
+    // Sandboxed scripts can't touch files at all
+    let output = jlox(&["--sandbox", &path, &directory.to_string_lossy()], "");
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "File access is disabled.\n[line 3]\n"
+    );
+    let output = jlox(&["run", "--sandbox", "-"], "print exists(\".\");");
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn debug_adapter_stops_on_entry_and_at_breakpoints() {
+    let (_directory, path) = script("dap-entry", "var a = 1;\nvar b = 2;\nprint a + b;\n");
+    let requests = [
+        ("initialize", serde_json::json!({ "adapterID": "jlox" })),
+        (
+            "launch",
+            serde_json::json!({ "program": path, "stopOnEntry": true }),
+        ),
+        (
+            "setBreakpoints",
+            serde_json::json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }] }),
+        ),
+        ("configurationDone", serde_json::json!({})),
+        ("continue", serde_json::json!({ "threadId": 1 })),
+        ("continue", serde_json::json!({ "threadId": 1 })),
+    ];
+
+    let output = jlox(&["dap"], &dap_requests(&requests));
+    let stdout = stdout(&output);
+    let entry = stdout
+        .find(r#""event":"stopped","body":{"reason":"entry""#)
+        .expect("no stop on entry");
+    let breakpoint = stdout
+        .find(r#""event":"stopped","body":{"reason":"breakpoint""#)
+        .expect("no stop at the breakpoint");
+    let printed = stdout.find(r#""output":"3\n""#).unwrap();
+    assert!(entry < breakpoint && breakpoint < printed, "{}", stdout);
+}
+
+#[test]
+fn sandboxed_debug_adapter() {
+    let (_directory, path) = script("dap-sandbox", "print exists(\".\");\n");
+    let requests = [
+        ("initialize", serde_json::json!({ "adapterID": "jlox" })),
+        (
+            "launch",
+            serde_json::json!({ "program": path, "noDebug": true }),
+        ),
+        ("configurationDone", serde_json::json!({})),
+    ];
+
+    let output = jlox(&["dap", "--sandbox"], &dap_requests(&requests));
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains(r#""output":"File access is disabled.\n"#),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains(r#""event":"exited","body":{"exitCode":70}"#));
+}
+
+#[test]
+fn sandbox_only_where_scripts_run() {
+    // Nothing is run when formatting or linting, so there's nothing to deny
+    for command in ["fmt", "lint", "lsp"] {
+        let output = jlox(&[command, "--sandbox", "-"], "");
+        assert_eq!(output.status.code(), Some(64), "{}", command);
+    }
+}
+
+#[test]
+fn sandboxed_imports() {
+    let (_directory, path) = script("secret", "top secret\n");
+    let source = format!("import \"{}\";", path);
+
+    // The module isn't read, so nothing of it shows in the error
+    let output = jlox(&["run", "--sandbox", "-"], &source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.ends_with("Can't import modules when file access is disabled.\n"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("top"), "{}", stderr);
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn sandboxed_prompt() {
+    // The prompt keeps its history in the home directory
+    let home = TempDir::new("home");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jlox"))
+        .arg("--sandbox")
+        .env("HOME", home.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("error running jlox");
+    let input = "print exists(\".\");\n:reset\nprint exists(\".\");\n";
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().expect("error running jlox");
+
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "File access is disabled.\n[line 1]\n".repeat(2)
+    );
+}
@@ -0,0 +1,169 @@
+//! Runs every `.lox` script under `tests/lox` through the `jlox` binary and
+//! checks its behaviour against annotations in the script, in the style of the
+//! Crafting Interpreters test suite:
+//!
+//! - `// expect: <output>` expects a line of standard output.
+//! - `// expect runtime error: <message>` expects the script to fail at
+//!   runtime on that line.
+//! - `// Error at '<lexeme>': <message>` expects a compile error on that
+//!   line, and `// [line N] Error ...` one on line `N`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const EXPECT_ERROR: &str = "// Error";
+const EXPECT_LINE_ERROR: &str = "// [line ";
+
+const EXIT_COMPILE_ERROR: i32 = 65;
+const EXIT_RUNTIME_ERROR: i32 = 70;
+
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    errors: Vec<String>,
+    exit_code: i32,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+
+            if let Some(position) = line.find(EXPECT) {
+                let output = &line[position + EXPECT.len()..];
+                expectations.output.push(output.to_string());
+            } else if let Some(position) = line.find(EXPECT_RUNTIME_ERROR) {
+                let message = &line[position + EXPECT_RUNTIME_ERROR.len()..];
+                expectations.errors.push(message.to_string());
+                expectations.errors.push(format!("[line {}]", line_number));
+                expectations.exit_code = EXIT_RUNTIME_ERROR;
+            } else if let Some(position) = line.find(EXPECT_ERROR) {
+                let error = &line[position + "// ".len()..];
+                expectations
+                    .errors
+                    .push(format!("[line {}] {}", line_number, error));
+                expectations.exit_code = EXIT_COMPILE_ERROR;
+            } else if let Some(position) = line.find(EXPECT_LINE_ERROR) {
+                let error = &line[position + "// ".len()..];
+                expectations.errors.push(error.to_string());
+                expectations.exit_code = EXIT_COMPILE_ERROR;
+            }
+        }
+
+        expectations
+    }
+}
+
+fn lox_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    for entry in std::fs::read_dir(dir).expect("error reading test directory") {
+        let path = entry.expect("error reading test directory").path();
+
+        if path.is_dir() {
+            files.extend(lox_files(&path));
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    files
+}
+
+fn run_test(path: &Path) -> Result<(), String> {
+    let source = std::fs::read_to_string(path).expect("error reading test file");
+    let expected = Expectations::parse(&source);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_jlox"))
+        .arg(path)
+        .output()
+        .expect("error running jlox");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let actual_output: Vec<&str> = stdout.lines().collect();
+    let actual_errors: Vec<&str> = stderr.lines().collect();
+    let exit_code = output.status.code().unwrap_or(-1);
+
+    let mut failures = vec![];
+
+    if actual_output != expected.output {
+        failures.push(format!(
+            "expected output {:#?}\n  got {:#?}",
+            expected.output, actual_output
+        ));
+    }
+
+    if actual_errors != expected.errors {
+        failures.push(format!(
+            "expected errors {:#?}\n  got {:#?}",
+            expected.errors, actual_errors
+        ));
+    }
+
+    if exit_code != expected.exit_code {
+        failures.push(format!(
+            "expected exit code {}, got {}",
+            expected.exit_code, exit_code
+        ));
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("\n"))
+    }
+}
+
+#[test]
+fn lox_suite() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let files = lox_files(&root);
+    assert!(!files.is_empty(), "no tests found in {}", root.display());
+
+    let failures: Vec<String> = files
+        .iter()
+        .filter_map(|path| {
+            run_test(path)
+                .err()
+                .map(|failure| format!("{}:\n{}", path.display(), failure))
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} tests failed:\n\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn formatter_is_idempotent() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+
+    for path in lox_files(&root) {
+        let source = std::fs::read_to_string(&path).expect("error reading test file");
+
+        // Tests of compile errors may not be formattable, but every other
+        // script must be
+        if Expectations::parse(&source).exit_code == EXIT_COMPILE_ERROR {
+            continue;
+        }
+        let formatted = jlox::formatter::format(&source)
+            .unwrap_or_else(|errors| panic!("formatting {} failed: {:?}", path.display(), errors));
+
+        assert_eq!(
+            jlox::formatter::format(&formatted).as_ref(),
+            Ok(&formatted),
+            "formatting {} again changed it",
+            path.display()
+        );
+    }
+}